@@ -1,16 +1,283 @@
 use crate::{
     data_providers::DataProvider,
-    error::AggregatorError,
+    error::{AggregatorError, ErrorKind, PolicyViolation, VenueError},
+    health::{CircuitBreaker, CircuitBreakerConfig, VenueCircuit},
+    metrics::Metrics,
     order_book::OrderBook,
-    types::{Exchange, Product},
+    types::{Exchange, Product, unix_time_ms},
 };
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::time::{Instant, timeout_at};
+use tracing::{Instrument, Span, debug, field, info_span, warn};
+
+// Default time budget for a whole aggregation round
+pub const DEFAULT_AGGREGATION_DEADLINE: Duration = Duration::from_secs(10);
+
+// Outcome of a single venue fetch within an aggregation round
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VenueStatus {
+    Success,
+    Failed,
+    TimedOut,
+    // The venue answered but its book was too old or out of sequence
+    Stale,
+    // Not fetched, the circuit breaker of the venue is open
+    Skipped,
+}
+
+impl std::fmt::Display for VenueStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VenueStatus::Success => write!(f, "success"),
+            VenueStatus::Failed => write!(f, "failed"),
+            VenueStatus::TimedOut => write!(f, "timed_out"),
+            VenueStatus::Stale => write!(f, "stale"),
+            VenueStatus::Skipped => write!(f, "skipped"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VenueReport {
+    pub venue: String,
+    pub status: VenueStatus,
+    // Time from dispatch until the venue answered, or until the deadline passed
+    pub latency: Duration,
+    // Number of price levels the venue returned on each side
+    pub bid_levels: usize,
+    pub ask_levels: usize,
+    // Kind and message of the error when the fetch did not succeed
+    pub error: Option<ErrorKind>,
+    pub error_message: Option<String>,
+    // HTTP status, venue error code and kind when the venue answered with an error
+    pub venue_error: Option<VenueError>,
+    // Whether fetching the venue again later may succeed
+    pub retryable: bool,
+}
+
+impl VenueReport {
+    fn success(venue: String, latency: Duration, book: &OrderBook) -> Self {
+        VenueReport {
+            venue,
+            status: VenueStatus::Success,
+            latency,
+            bid_levels: book.bids.len(),
+            ask_levels: book.asks.len(),
+            error: None,
+            error_message: None,
+            venue_error: None,
+            retryable: false,
+        }
+    }
+
+    fn from_error(venue: String, latency: Duration, error: &AggregatorError) -> Self {
+        let status = match error.kind() {
+            ErrorKind::Timeout => VenueStatus::TimedOut,
+            _ => VenueStatus::Failed,
+        };
+        let venue_error = match error {
+            AggregatorError::Venue(venue_error) => Some(venue_error.clone()),
+            _ => None,
+        };
+        VenueReport {
+            venue_error,
+            retryable: error.is_retryable(),
+            ..VenueReport::failure(venue, status, latency, error.kind(), error.to_string())
+        }
+    }
+
+    fn failure(
+        venue: String,
+        status: VenueStatus,
+        latency: Duration,
+        error: ErrorKind,
+        message: String,
+    ) -> Self {
+        VenueReport {
+            venue,
+            status,
+            latency,
+            bid_levels: 0,
+            ask_levels: 0,
+            error: Some(error),
+            error_message: Some(message),
+            venue_error: None,
+            retryable: matches!(error, ErrorKind::Timeout | ErrorKind::CircuitOpen),
+        }
+    }
+
+    fn stale(venue: String, latency: Duration, book: &OrderBook, reason: String) -> Self {
+        VenueReport {
+            status: VenueStatus::Stale,
+            error: Some(ErrorKind::Stale),
+            error_message: Some(reason),
+            ..VenueReport::success(venue, latency, book)
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == VenueStatus::Success
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrossKind {
+    // Best bid equals best ask
+    Locked,
+    // Best bid is above best ask
+    Crossed,
+}
+
+// Best bid of one venue at or above the best ask of another (or the same) venue
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossedMarket {
+    pub kind: CrossKind,
+    pub bid_venue: String,
+    pub bid_price: f64,
+    pub ask_venue: String,
+    pub ask_price: f64,
+}
+
+impl CrossedMarket {
+    // Find the widest cross between the per-venue books, if there is one
+    pub fn detect(books: &[(String, OrderBook)]) -> Option<Self> {
+        let (bid_venue, bid) = books
+            .iter()
+            .filter_map(|(venue, book)| book.best_bid().map(|level| (venue, level.price)))
+            .max_by_key(|(_, price)| *price)?;
+        let (ask_venue, ask) = books
+            .iter()
+            .filter_map(|(venue, book)| book.best_ask().map(|level| (venue, level.price)))
+            .min_by_key(|(_, price)| *price)?;
+        let kind = match bid.cmp(&ask) {
+            std::cmp::Ordering::Less => return None,
+            std::cmp::Ordering::Equal => CrossKind::Locked,
+            std::cmp::Ordering::Greater => CrossKind::Crossed,
+        };
+        Some(CrossedMarket {
+            kind,
+            bid_venue: bid_venue.clone(),
+            bid_price: bid.0,
+            ask_venue: ask_venue.clone(),
+            ask_price: ask.0,
+        })
+    }
+}
+
+// What to do when the venue books cross each other
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CrossPolicy {
+    // Keep every book and only report the condition
+    #[default]
+    Report,
+    // Drop the older of the two venues involved until nothing crosses
+    ExcludeStale,
+    // Match crossed levels of the consolidated book against each other
+    Uncross,
+}
+
+// Consolidated book together with the status of every venue that was queried
+#[derive(Debug, Clone)]
+pub struct AggregationReport {
+    pub book: OrderBook,
+    pub venues: Vec<VenueReport>,
+    // Books of the venues that made it into the consolidated book, keyed by provider name.
+    // Shared, so copies of the report do not copy every venue book.
+    pub venue_books: Vec<(String, Arc<OrderBook>)>,
+    // Cross between venue books found before the cross policy was applied
+    pub crossed: Option<CrossedMarket>,
+}
+
+impl AggregationReport {
+    // Names of the venues that did not answer before the deadline
+    pub fn timed_out_venues(&self) -> Vec<&str> {
+        self.venues
+            .iter()
+            .filter(|venue| venue.status == VenueStatus::TimedOut)
+            .map(|venue| venue.venue.as_str())
+            .collect()
+    }
+
+    // Reports of the venues that did not contribute to the consolidated book
+    pub fn failed_venues(&self) -> impl Iterator<Item = &VenueReport> {
+        self.venues.iter().filter(|venue| !venue.is_success())
+    }
+
+    // True when every queried venue contributed to the consolidated book
+    pub fn is_complete(&self) -> bool {
+        self.venues.iter().all(VenueReport::is_success)
+    }
+}
+
+// Conditions an aggregation round must satisfy before its book is returned.
+// The default policy accepts any round where at least one venue answered.
+#[derive(Debug, Clone, Default)]
+pub struct AggregationPolicy {
+    // Minimum number of venues that must contribute a book
+    pub min_venues: usize,
+    // Venues, by provider name, that must contribute a book
+    pub required_venues: Vec<String>,
+    // Minimum number of price levels on each side of the consolidated book
+    pub min_depth_per_side: usize,
+}
+
+impl AggregationPolicy {
+    // Check a finished round against the policy
+    pub fn check(&self, book: &OrderBook, venues: &[VenueReport]) -> Result<(), PolicyViolation> {
+        let available = venues.iter().filter(|venue| venue.is_success()).count();
+        if available < self.min_venues {
+            return Err(PolicyViolation::TooFewVenues {
+                required: self.min_venues,
+                available,
+            });
+        }
+        for required in &self.required_venues {
+            let answered = venues
+                .iter()
+                .any(|venue| venue.is_success() && venue.venue.eq_ignore_ascii_case(required));
+            if !answered {
+                return Err(PolicyViolation::MissingVenue(required.clone()));
+            }
+        }
+        if book.bids.len() < self.min_depth_per_side || book.asks.len() < self.min_depth_per_side {
+            return Err(PolicyViolation::InsufficientDepth {
+                required: self.min_depth_per_side,
+                bid_levels: book.bids.len(),
+                ask_levels: book.asks.len(),
+            });
+        }
+        Ok(())
+    }
+}
 
 pub struct OrderBookAggregator {
     // Data providers to fetch order book data from
     data_providers: Vec<Arc<dyn crate::data_providers::DataProvider>>,
     // OrderBook aggregation for this product ID
     product_id: Product,
+    // Venues that have not answered once this elapses are reported as timed out
+    deadline: Duration,
+    // Quorum and depth requirements for a successful aggregation
+    policy: AggregationPolicy,
+    // Books older than this are excluded from the consolidated book
+    max_staleness: Option<Duration>,
+    // Last sequence number seen per venue, used to reject out of order snapshots
+    last_sequences: Mutex<HashMap<String, u64>>,
+    // Handling of crossed or locked venue books
+    cross_policy: CrossPolicy,
+    // Registry updated after every round, if metrics are exported
+    metrics: Option<Arc<Metrics>>,
+    // Rounds started so far, numbers the aggregation spans
+    rounds: AtomicU64,
+    // Skips venues that keep failing, if enabled
+    circuit_breaker: Option<CircuitBreaker>,
 }
 
 impl OrderBookAggregator {
@@ -19,80 +286,802 @@ impl OrderBookAggregator {
         OrderBookAggregator {
             data_providers,
             product_id,
+            deadline: DEFAULT_AGGREGATION_DEADLINE,
+            policy: AggregationPolicy::default(),
+            max_staleness: None,
+            last_sequences: Mutex::new(HashMap::new()),
+            cross_policy: CrossPolicy::default(),
+            metrics: None,
+            rounds: AtomicU64::new(0),
+            circuit_breaker: None,
+        }
+    }
+
+    pub fn product_id(&self) -> &Product {
+        &self.product_id
+    }
+
+    // Override the overall aggregation deadline
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    // Set the quorum and depth policy checked after every aggregation
+    pub fn with_policy(mut self, policy: AggregationPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    // Exclude books whose exchange (or receive) timestamp is older than `max_staleness`
+    pub fn with_max_staleness(mut self, max_staleness: Duration) -> Self {
+        self.max_staleness = Some(max_staleness);
+        self
+    }
+
+    // Set how crossed or locked venue books are handled
+    pub fn with_cross_policy(mut self, cross_policy: CrossPolicy) -> Self {
+        self.cross_policy = cross_policy;
+        self
+    }
+
+    // Record venue fetches and round outcomes in `metrics`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    // Stop fetching venues that fail `config.failure_threshold` rounds in a row
+    pub fn with_circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.circuit_breaker = Some(CircuitBreaker::new(config));
+        self
+    }
+
+    // Circuit state of every venue fetched so far, empty without a circuit breaker
+    pub fn venue_health(&self) -> Vec<VenueCircuit> {
+        self.circuit_breaker
+            .as_ref()
+            .map(CircuitBreaker::health)
+            .unwrap_or_default()
+    }
+
+    // Reason the book should be excluded as stale at `now_ms`, the time of its provider, if any
+    fn check_staleness(&self, venue: &str, book: &OrderBook, now_ms: u64) -> Option<String> {
+        if let Some(sequence) = book.sequence {
+            let mut last_sequences = self.last_sequences.lock().unwrap();
+            let last = last_sequences.entry(venue.to_string()).or_insert(sequence);
+            if sequence < *last {
+                return Some(format!(
+                    "sequence {} is behind previously seen {}",
+                    sequence, last
+                ));
+            }
+            *last = sequence;
         }
+        let max_staleness = self.max_staleness?;
+        let age = Duration::from_millis(book.age_ms(now_ms));
+        if age > max_staleness {
+            return Some(format!(
+                "book is {} ms old, limit is {} ms",
+                age.as_millis(),
+                max_staleness.as_millis()
+            ));
+        }
+        None
     }
 
     // Fetch and aggregate order book data from all data providers
-    pub async fn fetch_and_aggregate_data(&self) -> Result<OrderBook, AggregatorError> {
+    pub async fn fetch_and_aggregate_data(&self) -> Result<AggregationReport, AggregatorError> {
+        let span = info_span!(
+            "aggregation",
+            product = %self.product_id,
+            round = self.rounds.fetch_add(1, Ordering::Relaxed) + 1,
+            venues = self.data_providers.len(),
+        );
+        let started = Instant::now();
+        let result = self.aggregate_round(started).instrument(span.clone()).await;
+        let _entered = span.enter();
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(report) => debug!(
+                elapsed_ms,
+                bid_levels = report.book.bids.len(),
+                ask_levels = report.book.asks.len(),
+                best_bid = report.book.best_bid().map(|level| level.price.0),
+                best_ask = report.book.best_ask().map(|level| level.price.0),
+                "round aggregated"
+            ),
+            Err(error) => warn!(elapsed_ms, kind = %error.kind(), %error, "round failed"),
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.record_round(&self.product_id, &result, started.elapsed());
+        }
+        result
+    }
+
+    async fn aggregate_round(
+        &self,
+        started: Instant,
+    ) -> Result<AggregationReport, AggregatorError> {
+        let deadline = started + self.deadline;
         let mut handles = Vec::new();
         for provider in &self.data_providers {
             let provider = Arc::clone(provider);
             let product_id = self.product_id.clone();
-            let handle = tokio::spawn(async move {
-                let name = provider.name().to_string();
-                match provider.fetch_order_book(product_id).await {
-                    Ok(book) => Ok(book),
-                    Err(e) => Err((name, e)),
+            let name = provider.name().to_string();
+            // Clients record the HTTP status of their request on this span
+            let span = info_span!(
+                "venue_fetch",
+                venue = %name,
+                product = %product_id,
+                http_status = field::Empty,
+            );
+            if let Some(breaker) = &self.circuit_breaker
+                && !breaker.allow(&name)
+            {
+                handles.push((name, span, None));
+                continue;
+            }
+            let handle = tokio::spawn(
+                async move {
+                    let started = Instant::now();
+                    let result = provider.fetch_order_book(product_id).await;
+                    (result, started.elapsed(), provider.now_ms())
                 }
-            });
-            handles.push(handle);
+                .instrument(span.clone()),
+            );
+            handles.push((name, span, Some(handle)));
         }
-        let mut aggregated_book = OrderBook::new(Exchange::AggregatedExchange);
-        // Every sucessfull data fetch task will be marked and counted as handled.
-        let mut marked_as_handled = 0;
-
-        for handle in handles {
-            match handle.await {
-                Ok(Ok(book)) => {
-                    marked_as_handled += 1;
-                    if aggregated_book.is_empty() {
-                        aggregated_book = book;
+        let mut venues = Vec::with_capacity(handles.len());
+        let mut spans = Vec::with_capacity(handles.len());
+        // Every sucessfull data fetch is kept for merging.
+        let mut books = Vec::with_capacity(handles.len());
+        // Time of the provider of every kept book when it answered
+        let mut clocks = Vec::with_capacity(handles.len());
+
+        for (provider_name, span, handle) in handles {
+            spans.push(span);
+            let Some(mut handle) = handle else {
+                venues.push(VenueReport::failure(
+                    provider_name,
+                    VenueStatus::Skipped,
+                    Duration::ZERO,
+                    ErrorKind::CircuitOpen,
+                    "Circuit open after repeated failures".to_string(),
+                ));
+                continue;
+            };
+            let report = match timeout_at(deadline, &mut handle).await {
+                Ok(Ok((Ok(book), latency, now_ms))) => {
+                    if let Some(reason) = self.check_staleness(&provider_name, &book, now_ms) {
+                        venues.push(VenueReport::stale(provider_name, latency, &book, reason));
                         continue;
                     }
-                    aggregated_book.merge(&book);
+                    let report = VenueReport::success(provider_name.clone(), latency, &book);
+                    clocks.push((provider_name.clone(), now_ms));
+                    books.push((provider_name, book));
+                    report
                 }
-                Ok(Err((provider_name, error))) => {
-                    println!(
-                        "Warning: Failed to fetch data from {}: {}",
-                        provider_name, error
-                    );
+                Ok(Ok((Err(error), latency, _))) => {
+                    VenueReport::from_error(provider_name, latency, &error)
                 }
-                Err(join_error) => {
-                    println!("Warning: Task join error: {}", join_error);
+                Ok(Err(join_error)) => VenueReport::failure(
+                    provider_name,
+                    VenueStatus::Failed,
+                    started.elapsed(),
+                    ErrorKind::Internal,
+                    join_error.to_string(),
+                ),
+                Err(_) => {
+                    // Deadline passed, stop waiting on this venue.
+                    handle.abort();
+                    VenueReport::failure(
+                        provider_name,
+                        VenueStatus::TimedOut,
+                        started.elapsed(),
+                        ErrorKind::Timeout,
+                        "No response before the aggregation deadline".to_string(),
+                    )
                 }
             };
+            venues.push(report);
+        }
+
+        if let Some(breaker) = &self.circuit_breaker {
+            for venue in &venues {
+                breaker.record(venue);
+            }
+        }
+        if let Some(metrics) = &self.metrics {
+            for venue in &venues {
+                metrics.record_venue(&self.product_id, venue);
+            }
+        }
+
+        let crossed = CrossedMarket::detect(&books);
+        if let Some(cross) = &crossed {
+            warn!(
+                kind = ?cross.kind,
+                bid_venue = %cross.bid_venue,
+                bid_price = cross.bid_price,
+                ask_venue = %cross.ask_venue,
+                ask_price = cross.ask_price,
+                "venue books cross"
+            );
+        }
+        if self.cross_policy == CrossPolicy::ExcludeStale {
+            exclude_crossed_stale_books(&mut books, &mut venues, &clocks);
+        }
+        for (venue, span) in venues.iter().zip(&spans) {
+            log_venue(span, venue);
         }
 
         // Only fail if ALL providers failed.
-        if marked_as_handled == 0 {
-            return Err(AggregatorError::AggregationFailed);
+        if books.is_empty() {
+            return Err(AggregatorError::AggregationFailed { venues });
+        }
+        let mut aggregated_book = OrderBook::new(Exchange::AggregatedExchange);
+        for (_, book) in &books {
+            aggregated_book.merge(book);
+        }
+        if self.cross_policy == CrossPolicy::Uncross {
+            aggregated_book.uncross();
+        }
+        if let Err(violation) = self.policy.check(&aggregated_book, &venues) {
+            return Err(AggregatorError::PolicyNotMet { violation, venues });
         }
 
-        Ok(aggregated_book)
+        Ok(AggregationReport {
+            book: aggregated_book,
+            venues,
+            venue_books: books
+                .into_iter()
+                .map(|(venue, book)| (venue, Arc::new(book)))
+                .collect(),
+            crossed,
+        })
+    }
+}
+
+// Outcome of one venue fetch, logged within its venue_fetch span
+fn log_venue(span: &Span, venue: &VenueReport) {
+    let _entered = span.enter();
+    let latency_ms = venue.latency.as_millis() as u64;
+    match venue.error {
+        // Already warned about when the circuit opened
+        Some(ErrorKind::CircuitOpen) => debug!("venue skipped"),
+        None => debug!(
+            latency_ms,
+            bid_levels = venue.bid_levels,
+            ask_levels = venue.ask_levels,
+            "venue fetched"
+        ),
+        Some(kind) => warn!(
+            latency_ms,
+            status = %venue.status,
+            %kind,
+            http_status = venue.venue_error.as_ref().map(|error| error.status),
+            retryable = venue.retryable,
+            error = venue.error_message.as_deref().unwrap_or_default(),
+            "venue excluded"
+        ),
+    }
+}
+
+// Drop the older book of every crossing pair and mark its venue as stale. Each book's age is
+// measured against the time of its own provider, as listed in `clocks`.
+fn exclude_crossed_stale_books(
+    books: &mut Vec<(String, OrderBook)>,
+    venues: &mut [VenueReport],
+    clocks: &[(String, u64)],
+) {
+    while let Some(cross) = CrossedMarket::detect(books) {
+        let age = |venue: &str| {
+            let now_ms = clocks
+                .iter()
+                .find(|(name, _)| name == venue)
+                .map_or_else(unix_time_ms, |(_, now_ms)| *now_ms);
+            books
+                .iter()
+                .find(|(name, _)| name == venue)
+                .map(|(_, book)| book.age_ms(now_ms))
+                .unwrap_or_default()
+        };
+        let (stale, other) = if age(&cross.bid_venue) >= age(&cross.ask_venue) {
+            (cross.bid_venue, cross.ask_venue)
+        } else {
+            (cross.ask_venue, cross.bid_venue)
+        };
+        books.retain(|(name, _)| *name != stale);
+        if let Some(report) = venues.iter_mut().find(|report| report.venue == stale) {
+            report.status = VenueStatus::Stale;
+            report.error = Some(ErrorKind::Stale);
+            report.error_message = Some(format!("book crossed {} and was older", other));
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::data_providers::{coinbase::CoinbaseExchange, gemini::GeminiExchange};
+    use crate::{
+        data_providers::{
+            mock::MockProvider,
+            replay::{ReplayProvider, SimulatedClock},
+        },
+        error::VenueErrorKind,
+        health::CircuitState,
+        order_book::FillSummary,
+        recorder::Record,
+    };
+    use ordered_float::OrderedFloat;
     use std::sync::Arc;
 
+    // Venue answering with a one level book after a fixed delay
+    fn delayed(name: &str, delay: Duration) -> Arc<MockProvider> {
+        Arc::new(MockProvider::new(name, one_level_book(Exchange::Coinbase)).with_latency(delay))
+    }
+
+    // Recorded fetch of a one level book on each side
+    fn recorded(venue: &str, exchange: Exchange, bid: f64, ask: f64) -> Record {
+        let mut book = OrderBook::new(exchange);
+        book.add_bid(bid, 1.0);
+        book.add_ask(ask, 1.0);
+        Record {
+            venue: venue.to_string(),
+            product: Product::BTCUSD.to_string(),
+            recorded_at_ms: book.received_at_ms,
+            latency_ms: 0,
+            book: Some(book),
+            error: None,
+        }
+    }
+
     #[tokio::test]
     async fn test_aggregator() {
-        let coinbase = Arc::new(CoinbaseExchange::new());
+        let coinbase = Arc::new(ReplayProvider::new(
+            "Coinbase",
+            vec![recorded("Coinbase", Exchange::Coinbase, 100.0, 101.0)],
+        ));
         let aggregator = OrderBookAggregator::new(vec![coinbase], Product::BTCUSD);
-        let aggregated_book = aggregator.fetch_and_aggregate_data().await.unwrap();
-        assert!(!aggregated_book.is_empty());
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        assert!(!report.book.is_empty());
     }
 
     #[tokio::test]
     async fn test_aggregator_with_multi_providers() {
-        let provider1 = Arc::new(CoinbaseExchange::new());
-        let provider2 = Arc::new(GeminiExchange::new());
-        // Here we can add more mock providers for testing
+        let records = vec![
+            recorded("Coinbase", Exchange::Coinbase, 100.0, 102.0),
+            recorded("Gemini", Exchange::Gemini, 100.5, 101.5),
+        ];
+        let provider1 = Arc::new(ReplayProvider::new("Coinbase", records.clone()));
+        let provider2 = Arc::new(ReplayProvider::new("Gemini", records));
         let aggregator = OrderBookAggregator::new(vec![provider1, provider2], Product::BTCUSD);
-        let aggregated_book = aggregator.fetch_and_aggregate_data().await.unwrap();
-        assert!(!aggregated_book.is_empty());
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        assert_eq!(report.book.best_bid().unwrap().exchange, Exchange::Gemini);
+        let fills = report.book.calculate_best_buy_offer(2.0).unwrap();
+        assert_eq!(FillSummary::from_fills(&fills).notional, 203.5);
+    }
+
+    #[tokio::test]
+    async fn test_aggregation_deadline() {
+        let fast = delayed("fast", Duration::from_millis(10));
+        let slow = delayed("slow", Duration::from_secs(30));
+        let aggregator = OrderBookAggregator::new(vec![fast, slow], Product::BTCUSD)
+            .with_deadline(Duration::from_millis(200));
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        // Book from the fast venue is returned, the slow one is marked as timed out
+        assert!(!report.book.is_empty());
+        assert_eq!(report.venues[0].status, VenueStatus::Success);
+        assert_eq!(report.timed_out_venues(), vec!["slow"]);
+    }
+
+    #[tokio::test]
+    async fn test_report_venue_details() {
+        let fast = delayed("fast", Duration::from_millis(20));
+        let slow = delayed("slow", Duration::from_secs(30));
+        let aggregator = OrderBookAggregator::new(vec![fast, slow], Product::BTCUSD)
+            .with_deadline(Duration::from_millis(200));
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        assert!(!report.is_complete());
+
+        let fast = &report.venues[0];
+        assert!(fast.is_success());
+        assert!(fast.latency >= Duration::from_millis(20));
+        assert_eq!((fast.bid_levels, fast.ask_levels), (1, 1));
+        assert!(fast.error.is_none());
+
+        let failed: Vec<_> = report.failed_venues().collect();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].venue, "slow");
+        assert_eq!(failed[0].error, Some(ErrorKind::Timeout));
+        assert!(failed[0].retryable);
+    }
+
+    #[tokio::test]
+    async fn test_report_venue_error() {
+        let maintenance = VenueError {
+            venue: Exchange::Gemini,
+            kind: VenueErrorKind::Unavailable,
+            status: 503,
+            code: Some("Maintenance".to_string()),
+            message: "Gemini is down for maintenance".to_string(),
+            retry_after: None,
+        };
+        let gemini = Arc::new(
+            MockProvider::new("Gemini", one_level_book(Exchange::Gemini))
+                .with_error(maintenance.clone().into()),
+        );
+        let aggregator = OrderBookAggregator::new(
+            vec![delayed("Coinbase", Duration::ZERO), gemini],
+            Product::BTCUSD,
+        );
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        let gemini = &report.venues[1];
+        assert_eq!(gemini.status, VenueStatus::Failed);
+        assert_eq!(gemini.error, Some(ErrorKind::Unavailable));
+        assert_eq!(gemini.venue_error, Some(maintenance));
+        assert!(gemini.retryable);
+    }
+
+    #[tokio::test]
+    async fn test_all_venues_timed_out() {
+        let slow = delayed("slow", Duration::from_secs(30));
+        let aggregator = OrderBookAggregator::new(vec![slow], Product::BTCUSD)
+            .with_deadline(Duration::from_millis(50));
+        let res = aggregator.fetch_and_aggregate_data().await;
+        assert!(matches!(
+            res,
+            Err(AggregatorError::AggregationFailed { .. })
+        ));
+    }
+
+    fn one_level_book(exchange: Exchange) -> OrderBook {
+        let mut book = OrderBook::new(exchange);
+        book.add_bid(100.0, 1.0);
+        book.add_ask(101.0, 1.0);
+        book
+    }
+
+    #[tokio::test]
+    async fn test_stale_book_excluded() {
+        let fresh = one_level_book(Exchange::Coinbase);
+        let mut old = one_level_book(Exchange::Gemini);
+        old.exchange_timestamp_ms = Some(unix_time_ms() - 60_000);
+        let aggregator = OrderBookAggregator::new(
+            vec![
+                Arc::new(MockProvider::new("Coinbase", fresh)),
+                Arc::new(MockProvider::new("Gemini", old)),
+            ],
+            Product::BTCUSD,
+        )
+        .with_max_staleness(Duration::from_secs(5));
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        assert_eq!(report.venues[0].status, VenueStatus::Success);
+        assert_eq!(report.venues[1].status, VenueStatus::Stale);
+        assert_eq!(report.venues[1].error, Some(ErrorKind::Stale));
+        // Only the fresh Coinbase level made it into the consolidated book
+        assert_eq!(report.book.bids[&OrderedFloat(100.0)].quantity.0, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_replayed_book_age_uses_replay_clock() {
+        let record_at = |recorded_at_ms: u64, received_at_ms: u64| {
+            let mut book = one_level_book(Exchange::Coinbase);
+            book.received_at_ms = received_at_ms;
+            Record {
+                venue: "Coinbase".to_string(),
+                product: "BTC-USD".to_string(),
+                recorded_at_ms,
+                latency_ms: 0,
+                book: Some(book),
+                error: None,
+            }
+        };
+        // Recorded long ago, but fresh when it was recorded
+        let clock = SimulatedClock::new(1_000_500);
+        let replay = ReplayProvider::new("Coinbase", vec![record_at(1_000_000, 1_000_000)])
+            .with_clock(clock.clone());
+        let aggregator = OrderBookAggregator::new(vec![Arc::new(replay)], Product::BTCUSD)
+            .with_max_staleness(Duration::from_secs(5));
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        assert_eq!(report.venues[0].status, VenueStatus::Success);
+
+        // Ten seconds later on the replay clock the same book is stale
+        clock.set_ms(1_010_000);
+        let res = aggregator.fetch_and_aggregate_data().await;
+        let Err(AggregatorError::AggregationFailed { venues }) = res else {
+            panic!("expected the stale book to be excluded, got {:?}", res);
+        };
+        assert_eq!(venues[0].status, VenueStatus::Stale);
+
+        // Sequential replay measures against the recording time of the served record
+        let replay = ReplayProvider::new("Coinbase", vec![record_at(2_000, 1_000)]);
+        let aggregator = OrderBookAggregator::new(vec![Arc::new(replay)], Product::BTCUSD)
+            .with_max_staleness(Duration::from_secs(5));
+        assert!(aggregator.fetch_and_aggregate_data().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_sequence_regression_is_stale() {
+        let mut book = one_level_book(Exchange::Coinbase);
+        book.sequence = Some(10);
+        let provider = Arc::new(MockProvider::new("Coinbase", book));
+        let aggregator = OrderBookAggregator::new(vec![provider.clone()], Product::BTCUSD);
+        assert!(aggregator.fetch_and_aggregate_data().await.is_ok());
+
+        provider.update_book(|book| book.sequence = Some(9));
+        let res = aggregator.fetch_and_aggregate_data().await;
+        assert!(matches!(
+            res,
+            Err(AggregatorError::AggregationFailed { .. })
+        ));
+
+        provider.update_book(|book| book.sequence = Some(11));
+        assert!(aggregator.fetch_and_aggregate_data().await.is_ok());
+    }
+
+    fn crossed_providers() -> Vec<Arc<dyn DataProvider>> {
+        let mut coinbase = OrderBook::new(Exchange::Coinbase);
+        coinbase.add_bid(100.0, 1.0);
+        coinbase.add_ask(101.0, 1.0);
+        let mut gemini = OrderBook::new(Exchange::Gemini);
+        gemini.add_bid(102.0, 0.5);
+        gemini.add_ask(103.0, 1.0);
+        gemini.received_at_ms -= 10_000;
+        vec![
+            Arc::new(MockProvider::new("Coinbase", coinbase)),
+            Arc::new(MockProvider::new("Gemini", gemini)),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_crossed_market_reported() {
+        let aggregator = OrderBookAggregator::new(crossed_providers(), Product::BTCUSD);
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        let crossed = report.crossed.as_ref().unwrap();
+        assert_eq!(crossed.kind, CrossKind::Crossed);
+        assert_eq!(
+            (crossed.bid_venue.as_str(), crossed.bid_price),
+            ("Gemini", 102.0)
+        );
+        assert_eq!(
+            (crossed.ask_venue.as_str(), crossed.ask_price),
+            ("Coinbase", 101.0)
+        );
+        // Report policy leaves the consolidated book as is
+        assert!(report.book.is_crossed());
+        assert!(report.is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_crossed_market_exclude_stale() {
+        let aggregator = OrderBookAggregator::new(crossed_providers(), Product::BTCUSD)
+            .with_cross_policy(CrossPolicy::ExcludeStale);
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        assert!(report.crossed.is_some());
+        assert!(!report.book.is_crossed());
+        // Gemini's book was received earlier, so it is the one dropped
+        assert_eq!(report.venues[1].status, VenueStatus::Stale);
+        assert_eq!(report.book.best_bid().unwrap().price.0, 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_crossed_market_exclude_stale_uses_provider_clocks() {
+        // Coinbase's book is the more recent one, but its replay clock has run further past it
+        let replay = |venue: &str, exchange, bid, ask, received_at_ms, now_ms| {
+            let mut record = recorded(venue, exchange, bid, ask);
+            record.recorded_at_ms = received_at_ms;
+            if let Some(book) = &mut record.book {
+                book.received_at_ms = received_at_ms;
+            }
+            let provider: Arc<dyn DataProvider> = Arc::new(
+                ReplayProvider::new(venue, vec![record]).with_clock(SimulatedClock::new(now_ms)),
+            );
+            provider
+        };
+        let providers = vec![
+            replay("Coinbase", Exchange::Coinbase, 100.0, 101.0, 2_000, 5_000),
+            replay("Gemini", Exchange::Gemini, 102.0, 103.0, 1_000, 1_500),
+        ];
+        let aggregator = OrderBookAggregator::new(providers, Product::BTCUSD)
+            .with_cross_policy(CrossPolicy::ExcludeStale);
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        // 3 s old on its clock against 0.5 s for Gemini
+        assert_eq!(report.venues[0].status, VenueStatus::Stale);
+        assert_eq!(report.venues[1].status, VenueStatus::Success);
+        assert_eq!(report.book.best_bid().unwrap().price.0, 102.0);
+    }
+
+    #[tokio::test]
+    async fn test_crossed_market_uncross() {
+        let aggregator = OrderBookAggregator::new(crossed_providers(), Product::BTCUSD)
+            .with_cross_policy(CrossPolicy::Uncross);
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        assert!(report.crossed.is_some());
+        assert!(!report.book.is_crossed());
+        assert!(report.is_complete());
+        // The 0.5 Gemini bid was matched against the 101 Coinbase ask
+        assert_eq!(report.book.best_ask().unwrap().quantity.0, 0.5);
+    }
+
+    fn fast_and_slow() -> Vec<Arc<dyn DataProvider>> {
+        vec![
+            delayed("Coinbase", Duration::from_millis(10)),
+            delayed("Gemini", Duration::from_secs(30)),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_policy_min_venues() {
+        let policy = AggregationPolicy {
+            min_venues: 2,
+            ..Default::default()
+        };
+        let aggregator = OrderBookAggregator::new(fast_and_slow(), Product::BTCUSD)
+            .with_deadline(Duration::from_millis(100))
+            .with_policy(policy);
+        let res = aggregator.fetch_and_aggregate_data().await;
+        let Err(AggregatorError::PolicyNotMet { violation, venues }) = res else {
+            panic!("expected a policy violation, got {:?}", res);
+        };
+        assert_eq!(
+            violation,
+            PolicyViolation::TooFewVenues {
+                required: 2,
+                available: 1
+            }
+        );
+        // The venue reports of the round come with the violation
+        assert_eq!(venues.len(), 2);
+        assert_eq!(venues[1].venue, "Gemini");
+        assert_eq!(venues[1].status, VenueStatus::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_policy_required_venues() {
+        let policy = AggregationPolicy {
+            required_venues: vec!["gemini".to_string()],
+            ..Default::default()
+        };
+        let aggregator = OrderBookAggregator::new(fast_and_slow(), Product::BTCUSD)
+            .with_deadline(Duration::from_millis(100))
+            .with_policy(policy);
+        let res = aggregator.fetch_and_aggregate_data().await;
+        assert!(matches!(
+            res,
+            Err(AggregatorError::PolicyNotMet {
+                violation: PolicyViolation::MissingVenue(venue),
+                ..
+            }) if venue == "gemini"
+        ));
+
+        // Coinbase answered, so requiring it is fine
+        let policy = AggregationPolicy {
+            min_venues: 1,
+            required_venues: vec!["coinbase".to_string()],
+            min_depth_per_side: 1,
+        };
+        let aggregator = OrderBookAggregator::new(fast_and_slow(), Product::BTCUSD)
+            .with_deadline(Duration::from_millis(100))
+            .with_policy(policy);
+        assert!(aggregator.fetch_and_aggregate_data().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_policy_min_depth() {
+        let policy = AggregationPolicy {
+            min_depth_per_side: 5,
+            ..Default::default()
+        };
+        let aggregator = OrderBookAggregator::new(fast_and_slow(), Product::BTCUSD)
+            .with_deadline(Duration::from_millis(100))
+            .with_policy(policy);
+        let res = aggregator.fetch_and_aggregate_data().await;
+        assert!(matches!(
+            res,
+            Err(AggregatorError::PolicyNotMet {
+                violation: PolicyViolation::InsufficientDepth { .. },
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_skips_failing_venue() {
+        let maintenance = VenueError {
+            venue: Exchange::Gemini,
+            kind: VenueErrorKind::Unavailable,
+            status: 503,
+            code: None,
+            message: "maintenance".to_string(),
+            retry_after: None,
+        };
+        let gemini = Arc::new(
+            MockProvider::new("Gemini", one_level_book(Exchange::Gemini))
+                .with_error(maintenance.into()),
+        );
+        let aggregator = OrderBookAggregator::new(
+            vec![delayed("Coinbase", Duration::ZERO), gemini.clone()],
+            Product::BTCUSD,
+        )
+        .with_circuit_breaker(CircuitBreakerConfig {
+            failure_threshold: 1,
+            open_duration: Duration::from_millis(50),
+        });
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        assert_eq!(report.venues[1].status, VenueStatus::Failed);
+
+        // Open circuit, Gemini is not fetched
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        assert_eq!(report.venues[1].status, VenueStatus::Skipped);
+        assert_eq!(report.venues[1].error, Some(ErrorKind::CircuitOpen));
+        assert_eq!(gemini.fetches(), 1);
+        let health = aggregator.venue_health();
+        assert_eq!(health[1].venue, "Gemini");
+        assert_eq!(health[1].state, CircuitState::Open);
+
+        // The probe after the open duration answers and closes the circuit
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let report = aggregator.fetch_and_aggregate_data().await.unwrap();
+        assert!(report.is_complete());
+        assert_eq!(gemini.fetches(), 2);
+        assert_eq!(aggregator.venue_health()[1].state, CircuitState::Closed);
+    }
+
+    // Log lines written by the test subscriber
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_venue_logged_within_its_spans() {
+        let capture = Capture::default();
+        let writer = capture.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let gemini = Arc::new(
+            MockProvider::new("Gemini", OrderBook::new(Exchange::Gemini))
+                .with_error(AggregatorError::ExchangeError("bad gateway".to_string())),
+        );
+        let aggregator = OrderBookAggregator::new(
+            vec![delayed("Coinbase", Duration::ZERO), gemini],
+            Product::BTCUSD,
+        );
+        aggregator.fetch_and_aggregate_data().await.unwrap();
+
+        let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let excluded = lines
+            .iter()
+            .find(|line| line["fields"]["message"] == "venue excluded")
+            .unwrap();
+        assert_eq!(excluded["fields"]["kind"], "exchange");
+        assert_eq!(excluded["spans"][0]["round"], 1);
+        assert_eq!(excluded["spans"][1]["venue"], "Gemini");
+        let fetched = lines
+            .iter()
+            .find(|line| line["fields"]["message"] == "venue fetched")
+            .unwrap();
+        assert_eq!(fetched["fields"]["bid_levels"], 1);
+        assert_eq!(fetched["span"]["venue"], "Coinbase");
     }
 }
@@ -0,0 +1,309 @@
+use crate::{
+    aggregator::AggregationReport,
+    order_book::{Level, OrderBook},
+};
+use serde::{Deserialize, Serialize};
+
+// Levels per side looked at by the depth and order flow imbalances when none are given
+pub const DEFAULT_IMBALANCE_LEVELS: usize = 5;
+// Distances from mid, in percent, of the default depth bands
+pub const DEFAULT_DEPTH_BANDS_PCT: [f64; 3] = [0.1, 0.5, 1.0];
+
+// What the analytics of a book look at
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalyticsConfig {
+    pub imbalance_levels: usize,
+    pub depth_bands_pct: Vec<f64>,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        AnalyticsConfig {
+            imbalance_levels: DEFAULT_IMBALANCE_LEVELS,
+            depth_bands_pct: DEFAULT_DEPTH_BANDS_PCT.to_vec(),
+        }
+    }
+}
+
+// Resting quantity within `pct` percent of mid on each side
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthBand {
+    pub pct: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub bid_quantity: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub bid_notional: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub ask_quantity: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub ask_notional: f64,
+}
+
+// Top of book and liquidity figures of one book. Figures needing both sides are None
+// when a side is empty.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookStats {
+    #[serde(with = "crate::decimal_string::option")]
+    pub best_bid: Option<f64>,
+    #[serde(with = "crate::decimal_string::option")]
+    pub best_ask: Option<f64>,
+    #[serde(with = "crate::decimal_string::option")]
+    pub mid: Option<f64>,
+    #[serde(with = "crate::decimal_string::option")]
+    pub spread: Option<f64>,
+    pub spread_bps: Option<f64>,
+    // Mid weighted towards the side with less quantity at the touch:
+    // (bid * ask_quantity + ask * bid_quantity) / (bid_quantity + ask_quantity)
+    #[serde(with = "crate::decimal_string::option")]
+    pub microprice: Option<f64>,
+    // (bid - ask) / (bid + ask) resting quantity over the top `imbalance_levels` levels,
+    // in [-1, 1], positive when bids outweigh asks
+    pub depth_imbalance: Option<f64>,
+    // Order flow imbalance over the top `imbalance_levels` levels since the previous book,
+    // in base quantity, see `order_flow_imbalance`. None without a previous book.
+    pub order_flow_imbalance: Option<f64>,
+    pub imbalance_levels: usize,
+    pub depth: Vec<DepthBand>,
+}
+
+impl BookStats {
+    // Stats of `book`, with the order flow since `previous` when the earlier book is known
+    pub fn new(book: &OrderBook, previous: Option<&OrderBook>, config: &AnalyticsConfig) -> Self {
+        let best_bid = book.best_bid();
+        let best_ask = book.best_ask();
+        let mid = book.mid_price();
+        let spread = match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) => Some(ask.price.0 - bid.price.0),
+            _ => None,
+        };
+        let microprice = match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) => {
+                let (bid_quantity, ask_quantity) = (bid.quantity.0, ask.quantity.0);
+                Some(
+                    (bid.price.0 * ask_quantity + ask.price.0 * bid_quantity)
+                        / (bid_quantity + ask_quantity),
+                )
+            }
+            _ => None,
+        };
+
+        let levels = config.imbalance_levels;
+        let bid_quantity: f64 = book
+            .bids
+            .values()
+            .rev()
+            .take(levels)
+            .map(|level| level.quantity.0)
+            .sum();
+        let ask_quantity: f64 = book
+            .asks
+            .values()
+            .take(levels)
+            .map(|level| level.quantity.0)
+            .sum();
+        let depth_imbalance = (bid_quantity + ask_quantity > 0.0)
+            .then(|| (bid_quantity - ask_quantity) / (bid_quantity + ask_quantity));
+
+        let depth = match mid {
+            Some(mid) => config
+                .depth_bands_pct
+                .iter()
+                .map(|&pct| depth_band(book, mid, pct))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        BookStats {
+            best_bid: best_bid.map(|level| level.price.0),
+            best_ask: best_ask.map(|level| level.price.0),
+            mid,
+            spread,
+            spread_bps: spread.zip(mid).map(|(spread, mid)| spread / mid * 10_000.0),
+            microprice,
+            depth_imbalance,
+            order_flow_imbalance: previous
+                .map(|previous| order_flow_imbalance(previous, book, levels)),
+            imbalance_levels: levels,
+            depth,
+        }
+    }
+}
+
+// Multi level order flow imbalance between two consecutive books: for each of the top `levels`
+// levels, the bid flow minus the ask flow. A side's flow at a level is the new quantity when the
+// price improved, the quantity change when it stayed, and minus the old quantity when it
+// worsened. Positive values mean buying pressure, in base quantity.
+pub fn order_flow_imbalance(previous: &OrderBook, book: &OrderBook, levels: usize) -> f64 {
+    let (mut old_bids, mut new_bids) = (previous.bids.values().rev(), book.bids.values().rev());
+    let (mut old_asks, mut new_asks) = (previous.asks.values(), book.asks.values());
+    let mut imbalance = 0.0;
+    for _ in 0..levels {
+        imbalance += level_flow(old_bids.next(), new_bids.next(), |new, old| new > old);
+        imbalance -= level_flow(old_asks.next(), new_asks.next(), |new, old| new < old);
+    }
+    imbalance
+}
+
+// Flow of one level of one side. A level missing from a book counts as the worst price.
+fn level_flow(old: Option<&Level>, new: Option<&Level>, improved: fn(f64, f64) -> bool) -> f64 {
+    match (old, new) {
+        (None, None) => 0.0,
+        (None, Some(new)) => new.quantity.0,
+        (Some(old), None) => -old.quantity.0,
+        (Some(old), Some(new)) if new.price == old.price => new.quantity.0 - old.quantity.0,
+        (Some(old), Some(new)) if improved(new.price.0, old.price.0) => new.quantity.0,
+        (Some(old), Some(_)) => -old.quantity.0,
+    }
+}
+
+fn depth_band(book: &OrderBook, mid: f64, pct: f64) -> DepthBand {
+    let low = mid * (1.0 - pct / 100.0);
+    let high = mid * (1.0 + pct / 100.0);
+    let bids = book
+        .bids
+        .values()
+        .rev()
+        .take_while(|level| level.price.0 >= low);
+    let asks = book.asks.values().take_while(|level| level.price.0 <= high);
+    let mut band = DepthBand {
+        pct,
+        bid_quantity: 0.0,
+        bid_notional: 0.0,
+        ask_quantity: 0.0,
+        ask_notional: 0.0,
+    };
+    for level in bids {
+        band.bid_quantity += level.quantity.0;
+        band.bid_notional += level.quantity.0 * level.price.0;
+    }
+    for level in asks {
+        band.ask_quantity += level.quantity.0;
+        band.ask_notional += level.quantity.0 * level.price.0;
+    }
+    band
+}
+
+// Stats of one venue's book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VenueStats {
+    pub venue: String,
+    #[serde(flatten)]
+    pub stats: BookStats,
+}
+
+// Stats of the consolidated book and of every venue book that went into it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketStats {
+    pub consolidated: BookStats,
+    pub venues: Vec<VenueStats>,
+}
+
+impl MarketStats {
+    // Stats of `report`. With the report of the round before, the order flow imbalance of the
+    // consolidated book and of every venue present in both rounds is included.
+    pub fn new(
+        report: &AggregationReport,
+        previous: Option<&AggregationReport>,
+        config: &AnalyticsConfig,
+    ) -> Self {
+        MarketStats {
+            consolidated: BookStats::new(
+                &report.book,
+                previous.map(|previous| &previous.book),
+                config,
+            ),
+            venues: report
+                .venue_books
+                .iter()
+                .map(|(venue, book)| {
+                    let previous = previous.and_then(|previous| {
+                        previous
+                            .venue_books
+                            .iter()
+                            .find(|(name, _)| name == venue)
+                            .map(|(_, book)| book.as_ref())
+                    });
+                    VenueStats {
+                        venue: venue.clone(),
+                        stats: BookStats::new(book, previous, config),
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Exchange;
+
+    #[test]
+    fn test_book_stats() {
+        let mut book = OrderBook::new(Exchange::Coinbase);
+        book.add_bid(99.0, 3.0);
+        book.add_bid(98.0, 1.0);
+        book.add_bid(90.0, 10.0);
+        book.add_ask(101.0, 1.0);
+        book.add_ask(110.0, 5.0);
+        let config = AnalyticsConfig {
+            imbalance_levels: 2,
+            depth_bands_pct: vec![2.0],
+        };
+        let stats = BookStats::new(&book, None, &config);
+        assert_eq!(stats.mid, Some(100.0));
+        assert_eq!(stats.spread_bps, Some(200.0));
+        // Three times more bid than ask at the touch pulls the price towards the ask
+        assert_eq!(stats.microprice, Some((99.0 + 3.0 * 101.0) / 4.0));
+        // 4 bid against 6 ask over two levels
+        assert_eq!(stats.depth_imbalance, Some(-0.2));
+        // Within 98..102: the 99 and 98 bids and the 101 ask
+        assert_eq!(stats.depth[0].bid_quantity, 4.0);
+        assert_eq!(stats.depth[0].ask_quantity, 1.0);
+        assert_eq!(stats.depth[0].ask_notional, 101.0);
+
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["mid"], "100");
+
+        let mut one_sided = OrderBook::new(Exchange::Gemini);
+        one_sided.add_bid(99.0, 1.0);
+        let stats = BookStats::new(&one_sided, None, &config);
+        assert_eq!(stats.best_ask, None);
+        assert_eq!(stats.spread_bps, None);
+        assert_eq!(stats.depth_imbalance, Some(1.0));
+        assert!(stats.depth.is_empty());
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["mid"], serde_json::Value::Null);
+        assert_eq!(serde_json::from_value::<BookStats>(json).unwrap(), stats);
+    }
+
+    #[test]
+    fn test_order_flow_imbalance() {
+        let mut previous = OrderBook::new(Exchange::Coinbase);
+        previous.add_bid(99.0, 3.0);
+        previous.add_bid(98.0, 1.0);
+        previous.add_ask(101.0, 1.0);
+        previous.add_ask(102.0, 5.0);
+        let mut book = OrderBook::new(Exchange::Coinbase);
+        // Best bid improved to 99.5, the 99 bid moved down a level and grew by 1
+        book.add_bid(99.5, 2.0);
+        book.add_bid(99.0, 4.0);
+        // Best ask unchanged but 0.5 smaller, the 102 ask gone
+        book.add_ask(101.0, 0.5);
+        assert_eq!(order_flow_imbalance(&previous, &book, 1), 2.0 + 0.5);
+        // Second level: bid 99 replaced 98 (+4), ask 102 disappeared (+5 of buying pressure)
+        assert_eq!(order_flow_imbalance(&previous, &book, 2), 2.5 + 4.0 + 5.0);
+        assert_eq!(order_flow_imbalance(&book, &book, 5), 0.0);
+
+        let config = AnalyticsConfig {
+            imbalance_levels: 1,
+            depth_bands_pct: Vec::new(),
+        };
+        let stats = BookStats::new(&book, Some(&previous), &config);
+        assert_eq!(stats.order_flow_imbalance, Some(2.5));
+        assert_eq!(
+            BookStats::new(&book, None, &config).order_flow_imbalance,
+            None
+        );
+    }
+}
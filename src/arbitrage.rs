@@ -0,0 +1,246 @@
+use crate::{order_book::OrderBook, types::Exchange};
+use serde::Serialize;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::mpsc;
+
+// Buy on one venue and sell on another for a positive profit after fees
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Opportunity {
+    pub buy_exchange: Exchange,
+    pub sell_exchange: Exchange,
+    // Quantity executable before the net bid drops below the net ask
+    #[serde(with = "crate::decimal_string")]
+    pub quantity: f64,
+    // Volume weighted prices of both legs, before fees
+    #[serde(with = "crate::decimal_string")]
+    pub buy_vwap: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub sell_vwap: f64,
+    // Sale proceeds minus purchase cost, after fees on both legs
+    #[serde(with = "crate::decimal_string")]
+    pub expected_profit: f64,
+}
+
+// Scans venue books for cross venue arbitrage net of taker fees
+#[derive(Debug, Clone, Default)]
+pub struct ArbitrageScanner {
+    // Taker fee per venue in basis points
+    fees_bps: HashMap<Exchange, f64>,
+    // Fee used for venues without an explicit entry
+    default_fee_bps: f64,
+    // Opportunities with a smaller expected profit are ignored
+    min_profit: f64,
+}
+
+impl ArbitrageScanner {
+    // Create a scanner with no fees
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Set the taker fee of a single venue
+    pub fn with_fee_bps(mut self, exchange: Exchange, fee_bps: f64) -> Self {
+        self.fees_bps.insert(exchange, fee_bps);
+        self
+    }
+
+    // Set the taker fee used for venues without an explicit fee
+    pub fn with_default_fee_bps(mut self, fee_bps: f64) -> Self {
+        self.default_fee_bps = fee_bps;
+        self
+    }
+
+    // Ignore opportunities whose expected profit is below `min_profit`
+    pub fn with_min_profit(mut self, min_profit: f64) -> Self {
+        self.min_profit = min_profit;
+        self
+    }
+
+    fn fee_rate(&self, exchange: Exchange) -> f64 {
+        self.fees_bps
+            .get(&exchange)
+            .copied()
+            .unwrap_or(self.default_fee_bps)
+            / 10_000.0
+    }
+
+    // Evaluate every ordered pair of venue books, most profitable first
+    pub fn scan(&self, books: &[&OrderBook]) -> Vec<Opportunity> {
+        let mut opportunities = Vec::new();
+        for buy in books {
+            for sell in books {
+                if buy.exchange == sell.exchange {
+                    continue;
+                }
+                if let Some(opportunity) = self.evaluate(buy, sell) {
+                    opportunities.push(opportunity);
+                }
+            }
+        }
+        opportunities.sort_by(|a, b| b.expected_profit.total_cmp(&a.expected_profit));
+        opportunities
+    }
+
+    // Walk the asks of `buy` against the bids of `sell` while buying stays cheaper than selling
+    pub fn evaluate(&self, buy: &OrderBook, sell: &OrderBook) -> Option<Opportunity> {
+        let buy_fee = self.fee_rate(buy.exchange);
+        let sell_fee = self.fee_rate(sell.exchange);
+        let mut asks = buy.asks.values();
+        let mut bids = sell.bids.values().rev();
+        let mut ask = asks.next().map(|level| (level.price.0, level.quantity.0));
+        let mut bid = bids.next().map(|level| (level.price.0, level.quantity.0));
+
+        let mut quantity = 0.0;
+        let mut cost = 0.0;
+        let mut proceeds = 0.0;
+        let mut profit = 0.0;
+        while let (Some((ask_price, ask_qty)), Some((bid_price, bid_qty))) = (ask, bid) {
+            let net_ask = ask_price * (1.0 + buy_fee);
+            let net_bid = bid_price * (1.0 - sell_fee);
+            if net_bid <= net_ask {
+                break;
+            }
+            let matched = ask_qty.min(bid_qty);
+            quantity += matched;
+            cost += matched * ask_price;
+            proceeds += matched * bid_price;
+            profit += matched * (net_bid - net_ask);
+
+            ask = if ask_qty > matched {
+                Some((ask_price, ask_qty - matched))
+            } else {
+                asks.next().map(|level| (level.price.0, level.quantity.0))
+            };
+            bid = if bid_qty > matched {
+                Some((bid_price, bid_qty - matched))
+            } else {
+                bids.next().map(|level| (level.price.0, level.quantity.0))
+            };
+        }
+
+        if quantity <= 0.0 || profit < self.min_profit {
+            return None;
+        }
+        Some(Opportunity {
+            buy_exchange: buy.exchange,
+            sell_exchange: sell.exchange,
+            quantity,
+            buy_vwap: cost / quantity,
+            sell_vwap: proceeds / quantity,
+            expected_profit: profit,
+        })
+    }
+
+    // Keep the latest book per venue and evaluate the pairs of every updated venue until either
+    // channel closes. Opportunities of one update are sent most profitable first.
+    pub async fn run(
+        self,
+        mut books: mpsc::Receiver<Arc<OrderBook>>,
+        opportunities: mpsc::Sender<Opportunity>,
+    ) {
+        let mut latest: HashMap<Exchange, Arc<OrderBook>> = HashMap::new();
+        while let Some(book) = books.recv().await {
+            // Only pairs involving the updated venue can have changed
+            let mut found: Vec<Opportunity> = latest
+                .values()
+                .filter(|other| other.exchange != book.exchange)
+                .flat_map(|other| [self.evaluate(&book, other), self.evaluate(other, &book)])
+                .flatten()
+                .collect();
+            found.sort_by(|a, b| b.expected_profit.total_cmp(&a.expected_profit));
+            latest.insert(book.exchange, book);
+            for opportunity in found {
+                if opportunities.send(opportunity).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggregator::OrderBookAggregator,
+        data_providers::{DataProvider, mock::MockProvider},
+        feed::BookFeed,
+        types::Product,
+    };
+    use std::time::Duration;
+
+    fn books() -> (OrderBook, OrderBook) {
+        let mut coinbase = OrderBook::new(Exchange::Coinbase);
+        coinbase.add_ask(100.0, 1.0);
+        coinbase.add_ask(100.5, 2.0);
+        coinbase.add_bid(99.0, 1.0);
+        let mut gemini = OrderBook::new(Exchange::Gemini);
+        gemini.add_bid(101.0, 0.5);
+        gemini.add_bid(100.8, 1.0);
+        gemini.add_ask(102.0, 1.0);
+        (coinbase, gemini)
+    }
+
+    #[test]
+    fn test_scan_walks_both_books() {
+        let (coinbase, gemini) = books();
+        let opportunities = ArbitrageScanner::new().scan(&[&coinbase, &gemini]);
+        assert_eq!(opportunities.len(), 1);
+        let opportunity = &opportunities[0];
+        assert_eq!(opportunity.buy_exchange, Exchange::Coinbase);
+        assert_eq!(opportunity.sell_exchange, Exchange::Gemini);
+        // 0.5 @ 100 -> 101, 0.5 @ 100 -> 100.8, 0.5 @ 100.5 -> 100.8
+        assert_eq!(opportunity.quantity, 1.5);
+        assert!((opportunity.expected_profit - 1.05).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fees_remove_opportunity() {
+        let (coinbase, gemini) = books();
+        // 50 bps on each leg costs about 1 USD per unit, more than the 1 USD spread
+        let scanner = ArbitrageScanner::new().with_default_fee_bps(50.0);
+        assert!(scanner.scan(&[&coinbase, &gemini]).is_empty());
+
+        // With fees on Gemini only, the first 0.5 @ 101 still pays
+        let scanner = ArbitrageScanner::new().with_fee_bps(Exchange::Gemini, 90.0);
+        let opportunity = scanner.evaluate(&coinbase, &gemini).unwrap();
+        assert_eq!(opportunity.quantity, 0.5);
+    }
+
+    #[tokio::test]
+    async fn test_run_over_streamed_books() {
+        let (coinbase, gemini) = books();
+        let (book_tx, book_rx) = mpsc::channel(4);
+        let (opportunity_tx, mut opportunity_rx) = mpsc::channel(4);
+        tokio::spawn(ArbitrageScanner::new().run(book_rx, opportunity_tx));
+
+        book_tx.send(Arc::new(coinbase.clone())).await.unwrap();
+        book_tx.send(Arc::new(gemini)).await.unwrap();
+        let opportunity = opportunity_rx.recv().await.unwrap();
+        assert_eq!(opportunity.sell_exchange, Exchange::Gemini);
+
+        // An update that closes the gap reports nothing more
+        let mut closed = coinbase;
+        closed.asks.clear();
+        closed.add_ask(103.0, 1.0);
+        book_tx.send(Arc::new(closed)).await.unwrap();
+        drop(book_tx);
+        assert!(opportunity_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_run_over_feed_venue_books() {
+        let (coinbase, gemini) = books();
+        let providers: Vec<Arc<dyn DataProvider>> = vec![
+            Arc::new(MockProvider::new("Coinbase", coinbase)),
+            Arc::new(MockProvider::new("Gemini", gemini)),
+        ];
+        let aggregator = OrderBookAggregator::new(providers, Product::BTCUSD);
+        let feed = BookFeed::spawn(aggregator, Duration::from_secs(60));
+        let (opportunity_tx, mut opportunity_rx) = mpsc::channel(4);
+        tokio::spawn(ArbitrageScanner::new().run(feed.venue_books(), opportunity_tx));
+        let opportunity = opportunity_rx.recv().await.unwrap();
+        assert_eq!(opportunity.buy_exchange, Exchange::Coinbase);
+        assert_eq!(opportunity.quantity, 1.5);
+    }
+}
@@ -0,0 +1,406 @@
+use crate::{
+    aggregator::OrderBookAggregator,
+    data_providers::{
+        DataProvider,
+        replay::{ReplayProvider, SimulatedClock},
+    },
+    error::AggregatorError,
+    order_book::{FillSummary, OrderBook, OrderDetails},
+    recorder::Record,
+    types::{Exchange, Product, Side},
+};
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+
+// Venue books of one aggregation round at a point of simulated time. Books that did not
+// change since the previous snapshot are shared with it.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub time_ms: u64,
+    pub venue_books: Vec<(String, Arc<OrderBook>)>,
+}
+
+impl Snapshot {
+    // Consolidated book of the given venues, of all venues when `venues` is empty
+    pub fn consolidated(&self, venues: &[String]) -> OrderBook {
+        let mut book = OrderBook::new(Exchange::AggregatedExchange);
+        for (venue, venue_book) in &self.venue_books {
+            if venues.is_empty() || venues.iter().any(|v| v.eq_ignore_ascii_case(venue)) {
+                book.merge(venue_book);
+            }
+        }
+        book
+    }
+}
+
+// Consolidated books over time, replayed from recorded venue fetches
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    pub snapshots: Vec<Snapshot>,
+}
+
+// How an order is worked through the timeline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    // Sweep the whole order against the first book
+    Immediate,
+    // Equal child orders, the first one at the start and then one every `interval`
+    Twap { slices: usize, interval: Duration },
+}
+
+// Parent order evaluated by a backtest
+#[derive(Debug, Clone)]
+pub struct Execution {
+    pub side: Side,
+    pub quantity: f64,
+    pub strategy: Strategy,
+    // Provider names to trade on, every recorded venue when empty
+    pub venues: Vec<String>,
+}
+
+// One child order as it would have executed
+#[derive(Debug, Clone, Serialize)]
+pub struct ChildFill {
+    pub time_ms: u64,
+    #[serde(with = "crate::decimal_string")]
+    pub mid: f64,
+    #[serde(flatten)]
+    pub summary: FillSummary,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VenueShare {
+    pub exchange: String,
+    #[serde(with = "crate::decimal_string")]
+    pub quantity: f64,
+    // Fraction of the parent quantity filled on this venue
+    pub share: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestResult {
+    pub side: Side,
+    // Totals of every child order, the average price is the realized VWAP
+    #[serde(flatten)]
+    pub summary: FillSummary,
+    // Mid price of the book the first child order executed against
+    #[serde(with = "crate::decimal_string")]
+    pub arrival_mid: f64,
+    // Cost of the execution against the arrival mid, positive when worse than mid
+    pub slippage_bps: f64,
+    pub venues: Vec<VenueShare>,
+    pub children: Vec<ChildFill>,
+}
+
+impl Timeline {
+    // Aggregate the recorded venue books every `step`, from the first record to the last.
+    // Rounds before every venue has a record only include the venues recorded so far.
+    pub async fn replay(
+        records: Vec<Record>,
+        product: Product,
+        step: Duration,
+    ) -> Result<Self, AggregatorError> {
+        let product_name = product.to_string();
+        let records: Vec<Record> = records
+            .into_iter()
+            .filter(|record| record.product == product_name)
+            .collect();
+        let (Some(start), Some(end)) = (
+            records.iter().map(|record| record.recorded_at_ms).min(),
+            records.iter().map(|record| record.recorded_at_ms).max(),
+        ) else {
+            return Ok(Timeline::default());
+        };
+
+        let mut venues: Vec<String> = records.iter().map(|record| record.venue.clone()).collect();
+        venues.sort();
+        venues.dedup();
+        let clock = SimulatedClock::new(start);
+        let providers: Vec<Arc<dyn DataProvider>> = venues
+            .iter()
+            .map(|venue| -> Arc<dyn DataProvider> {
+                Arc::new(ReplayProvider::new(venue, records.clone()).with_clock(clock.clone()))
+            })
+            .collect();
+        let aggregator = OrderBookAggregator::new(providers, product);
+
+        let step_ms = (step.as_millis() as u64).max(1);
+        let mut snapshots = Vec::new();
+        let mut time_ms = start;
+        while time_ms <= end {
+            clock.set_ms(time_ms);
+            // Rounds where no venue has a usable book are skipped
+            if let Ok(report) = aggregator.fetch_and_aggregate_data().await {
+                let previous = snapshots
+                    .last()
+                    .map(|snapshot: &Snapshot| &snapshot.venue_books);
+                let venue_books = report
+                    .venue_books
+                    .into_iter()
+                    .map(|(venue, book)| {
+                        // A venue without a newer record serves the same book again, which
+                        // is shared with the previous snapshot instead of kept twice
+                        let unchanged = previous
+                            .and_then(|books| books.iter().find(|(name, _)| *name == venue))
+                            .filter(|(_, previous)| *previous == book)
+                            .map(|(_, previous)| previous.clone());
+                        (venue, unchanged.unwrap_or(book))
+                    })
+                    .collect();
+                snapshots.push(Snapshot {
+                    time_ms,
+                    venue_books,
+                });
+            }
+            time_ms += step_ms;
+        }
+        Ok(Timeline { snapshots })
+    }
+
+    // Latest snapshot at or before `time_ms`
+    pub fn at(&self, time_ms: u64) -> Option<&Snapshot> {
+        self.snapshots
+            .iter()
+            .take_while(|snapshot| snapshot.time_ms <= time_ms)
+            .last()
+    }
+
+    // Execute `execution` against the timeline. Child orders do not move the book, every
+    // child sweeps the book as it was recorded. Children scheduled after the last snapshot
+    // execute against it. A price quoted by several venues fills each of them in proportion
+    // to its quantity in the venue books.
+    pub fn run(&self, execution: &Execution) -> Result<BacktestResult, AggregatorError> {
+        if !(execution.quantity.is_finite() && execution.quantity > 0.0) {
+            return Err(AggregatorError::InvalidSchedule(format!(
+                "quantity must be positive, got {}",
+                execution.quantity
+            )));
+        }
+        let start = self
+            .snapshots
+            .first()
+            .ok_or_else(|| AggregatorError::NoRecordedBook("for the product".to_string()))?
+            .time_ms;
+        let schedule: Vec<(u64, f64)> = match execution.strategy {
+            Strategy::Immediate => vec![(start, execution.quantity)],
+            Strategy::Twap { slices: 0, .. } => {
+                return Err(AggregatorError::InvalidSchedule(
+                    "at least one slice required".to_string(),
+                ));
+            }
+            Strategy::Twap { slices, interval } => {
+                let child = execution.quantity / slices as f64;
+                (0..slices)
+                    .map(|i| (start + i as u64 * interval.as_millis() as u64, child))
+                    .collect()
+            }
+        };
+
+        let mut fills: Vec<OrderDetails> = Vec::new();
+        let mut children = Vec::with_capacity(schedule.len());
+        for (time_ms, quantity) in schedule {
+            let snapshot = self
+                .at(time_ms)
+                .ok_or_else(|| AggregatorError::NoRecordedBook(format!("at {}", time_ms)))?;
+            let book = snapshot.consolidated(&execution.venues);
+            let mid = book.mid_price().ok_or_else(|| {
+                AggregatorError::InsufficientLiquidity(format!("No two sided book at {}", time_ms))
+            })?;
+            let child_fills = match execution.side {
+                Side::Buy => book.calculate_best_buy_offer(quantity)?,
+                Side::Sell => book.calculate_best_sell_offer(quantity)?,
+            };
+            children.push(ChildFill {
+                time_ms,
+                mid,
+                summary: FillSummary::from_fills(&child_fills),
+            });
+            fills.extend(child_fills);
+        }
+
+        let summary = FillSummary::from_fills(&fills);
+        let arrival_mid = children[0].mid;
+        Ok(BacktestResult {
+            side: execution.side,
+            summary,
+            arrival_mid,
+            slippage_bps: summary.slippage_bps(execution.side, arrival_mid),
+            venues: venue_shares(&fills, summary.quantity),
+            children,
+        })
+    }
+}
+
+fn venue_shares(fills: &[OrderDetails], total: f64) -> Vec<VenueShare> {
+    let mut shares: Vec<VenueShare> = Vec::new();
+    for fill in fills {
+        match shares
+            .iter_mut()
+            .find(|share| share.exchange == fill.exchange)
+        {
+            Some(share) => share.quantity += fill.quantity,
+            None => shares.push(VenueShare {
+                exchange: fill.exchange.clone(),
+                quantity: fill.quantity,
+                share: 0.0,
+            }),
+        }
+    }
+    for share in &mut shares {
+        share.share = share.quantity / total;
+    }
+    shares.sort_by(|a, b| b.quantity.total_cmp(&a.quantity));
+    shares
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    fn record(venue: &str, exchange: Exchange, time_ms: u64, ask: f64) -> Record {
+        let mut book = OrderBook::new(exchange);
+        book.add_bid(ask - 2.0, 10.0);
+        book.add_ask(ask, 1.0);
+        book.add_ask(ask + 10.0, 10.0);
+        Record {
+            venue: venue.to_string(),
+            product: "BTC-USD".to_string(),
+            recorded_at_ms: time_ms,
+            latency_ms: 0,
+            book: Some(book),
+            error: None,
+        }
+    }
+
+    async fn timeline() -> Timeline {
+        let records = vec![
+            record("Coinbase", Exchange::Coinbase, 1_000, 101.0),
+            record("Gemini", Exchange::Gemini, 1_000, 102.0),
+            // Coinbase refills its best ask a second later
+            record("Coinbase", Exchange::Coinbase, 2_000, 101.0),
+        ];
+        Timeline::replay(records, Product::BTCUSD, Duration::from_secs(1))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_immediate_sweep() {
+        let timeline = timeline().await;
+        assert_eq!(timeline.snapshots.len(), 2);
+        let result = timeline
+            .run(&Execution {
+                side: Side::Buy,
+                quantity: 2.0,
+                strategy: Strategy::Immediate,
+                venues: Vec::new(),
+            })
+            .unwrap();
+        // 1 @ 101 on Coinbase, 1 @ 102 on Gemini, mid between 100 and 101
+        assert_eq!(result.summary.average_price, 101.5);
+        assert_eq!(result.arrival_mid, 100.5);
+        assert!((result.slippage_bps - 1.0 / 100.5 * 10_000.0).abs() < 1e-9);
+        assert_eq!(result.venues.len(), 2);
+        assert_eq!(result.venues[0].share, 0.5);
+        // Gemini has no newer record, its book is shared by both snapshots
+        let gemini = |snapshot: &Snapshot| {
+            snapshot
+                .venue_books
+                .iter()
+                .find(|(venue, _)| venue == "Gemini")
+                .map(|(_, book)| book.clone())
+                .unwrap()
+        };
+        assert!(Arc::ptr_eq(
+            &gemini(&timeline.snapshots[0]),
+            &gemini(&timeline.snapshots[1])
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_shared_price_split_across_venues() {
+        let records = vec![
+            record("Coinbase", Exchange::Coinbase, 1_000, 101.0),
+            record("Gemini", Exchange::Gemini, 1_000, 101.0),
+        ];
+        let timeline = Timeline::replay(records, Product::BTCUSD, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let result = timeline
+            .run(&Execution {
+                side: Side::Buy,
+                quantity: 1.0,
+                strategy: Strategy::Immediate,
+                venues: Vec::new(),
+            })
+            .unwrap();
+        // Both venues quote 1 @ 101, so each fills half of the order
+        assert_eq!(result.summary.average_price, 101.0);
+        let shares: Vec<f64> = result.venues.iter().map(|venue| venue.share).collect();
+        assert_eq!(shares, [0.5, 0.5]);
+    }
+
+    #[tokio::test]
+    async fn test_twap_and_venue_subset() {
+        let timeline = timeline().await;
+        let twap = Execution {
+            side: Side::Buy,
+            quantity: 2.0,
+            strategy: Strategy::Twap {
+                slices: 2,
+                interval: Duration::from_secs(1),
+            },
+            venues: vec!["coinbase".to_string()],
+        };
+        let result = timeline.run(&twap).unwrap();
+        // Both slices take the refilled 101 Coinbase ask
+        assert_eq!(result.children.len(), 2);
+        assert_eq!(result.summary.average_price, 101.0);
+        assert_eq!(result.venues[0].exchange, "coinbase");
+        assert_eq!(result.venues[0].share, 1.0);
+
+        let immediate = Execution {
+            strategy: Strategy::Immediate,
+            ..twap
+        };
+        // Swept at once, the second unit goes 10 higher
+        assert_eq!(
+            timeline.run(&immediate).unwrap().summary.average_price,
+            106.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_backtests() {
+        let execution = Execution {
+            side: Side::Sell,
+            quantity: 1.0,
+            strategy: Strategy::Immediate,
+            venues: Vec::new(),
+        };
+        let error = Timeline::default().run(&execution).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NoRecordedBook);
+
+        let timeline = timeline().await;
+        let zero = Execution {
+            quantity: 0.0,
+            ..execution.clone()
+        };
+        assert!(matches!(
+            timeline.run(&zero),
+            Err(AggregatorError::InvalidSchedule(_))
+        ));
+        let no_slices = Execution {
+            strategy: Strategy::Twap {
+                slices: 0,
+                interval: Duration::from_secs(1),
+            },
+            ..execution
+        };
+        assert!(matches!(
+            timeline.run(&no_slices),
+            Err(AggregatorError::InvalidSchedule(_))
+        ));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
-    data_providers::DataProvider,
-    error::AggregatorError,
+    data_providers::{DEFAULT_REQUEST_TIMEOUT, DataProvider, parse_body, retry_after},
+    error::{AggregatorError, VenueError, VenueErrorKind},
     order_book::OrderBook,
     rate_limiter::RateLimiter,
     types::{Exchange, Product},
@@ -8,13 +8,48 @@ use crate::{
 use async_trait::async_trait;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tokio::sync::Mutex;
 // Coinbase API response structures
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CoinbaseBookResponse {
     bids: Vec<(String, String, u32)>,
     asks: Vec<(String, String, u32)>,
+    #[serde(default)]
+    sequence: Option<u64>,
+}
+
+// Body of a Coinbase error answer
+#[derive(Debug, Clone, Deserialize)]
+struct CoinbaseErrorResponse {
+    message: String,
+}
+
+// Coinbase has no error codes, only a message. Unlisted products answer 404 NotFound.
+async fn error_response(response: reqwest::Response) -> AggregatorError {
+    let status = response.status().as_u16();
+    let retry_after = retry_after(response.headers());
+    let body = match response.text().await {
+        Ok(body) => body,
+        Err(error) => return error.into(),
+    };
+    let message = serde_json::from_str::<CoinbaseErrorResponse>(&body)
+        .map(|error| error.message)
+        .unwrap_or(body);
+    let kind = if message == "NotFound" {
+        VenueErrorKind::UnknownSymbol
+    } else {
+        VenueErrorKind::from_status(status)
+    };
+    VenueError {
+        venue: Exchange::Coinbase,
+        kind,
+        status,
+        code: None,
+        message,
+        retry_after,
+    }
+    .into()
 }
 
 // Coinbase Exchange Data Provider
@@ -22,22 +57,41 @@ pub struct CoinbaseExchange {
     client: reqwest::Client,
     rate_limiter: Arc<Mutex<RateLimiter>>,
     base_url: Url,
+    request_timeout: Duration,
 }
 
 impl CoinbaseExchange {
+    // Client for the API base URL set in COINBASE_API_BASE_URL
     pub fn new() -> Self {
         let url =
             dotenvy::var("COINBASE_API_BASE_URL").expect("Failed to get coinbase url from env");
         let base_url = Url::parse(&url).expect("Invalid Coinbase API base URL");
+        Self::from_base_url(base_url)
+    }
 
+    // Client for an explicit API base URL, e.g. a local stub
+    pub fn from_base_url(base_url: Url) -> Self {
         CoinbaseExchange {
             client: reqwest::Client::new(),
             rate_limiter: Arc::new(Mutex::new(
                 RateLimiter::new(1, 2), // 1 requests per 2 seconds.
             )),
             base_url,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
         }
     }
+
+    // Override the per-request HTTP timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+}
+
+impl Default for CoinbaseExchange {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // Implement DataProvider trait for CoinbaseExchange
@@ -64,18 +118,20 @@ impl DataProvider for CoinbaseExchange {
             .client
             .get(&url)
             .header("User-Agent", "order-book-aggregator/1.0")
+            .timeout(self.request_timeout)
             .send()
             .await?;
 
+        // Recorded on the venue_fetch span the aggregator opens around this call
+        let status = response.status().as_u16();
+        tracing::Span::current().record("http_status", status);
+        tracing::debug!(http_status = status, %url, "venue responded");
         if !response.status().is_success() {
-            let err = response.text().await?;
-            return Err(AggregatorError::ExchangeError(format!(
-                "Failed to fetch order book from Coinbase :  {}",
-                err
-            )));
+            return Err(error_response(response).await);
         }
-        let book: CoinbaseBookResponse = response.json().await?;
+        let book: CoinbaseBookResponse = parse_body(Exchange::Coinbase, response).await?;
         let mut order_book = OrderBook::new(Exchange::Coinbase);
+        order_book.sequence = book.sequence;
         // Add bids to order book
         for level in &book.bids {
             if let (Ok(price), Ok(quantity)) = (level.0.parse::<f64>(), level.1.parse::<f64>()) {
@@ -96,21 +152,91 @@ impl DataProvider for CoinbaseExchange {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        data_providers::stub::{ExchangeStub, StubResponse},
+        error::ErrorKind,
+    };
+
+    async fn stub() -> ExchangeStub {
+        let mut book = OrderBook::new(Exchange::Coinbase);
+        book.add_bid(100.0, 1.5);
+        book.add_bid(99.5, 2.0);
+        book.add_ask(101.0, 0.25);
+        book.sequence = Some(42);
+        ExchangeStub::start(book, OrderBook::new(Exchange::Gemini))
+            .await
+            .unwrap()
+    }
 
     #[tokio::test]
     async fn test_fetch_coinbase_order_book() {
-        let provider = CoinbaseExchange::new();
+        let stub = stub().await;
+        let provider = CoinbaseExchange::from_base_url(stub.base_url());
         let book = provider.fetch_order_book(Product::BTCUSD).await.unwrap();
-        assert!(!book.is_empty());
+        assert_eq!(book.exchange, Exchange::Coinbase);
+        assert_eq!(book.sequence, Some(42));
+        assert_eq!(book.bids.len(), 2);
+        assert_eq!(book.best_bid().unwrap().quantity.0, 1.5);
+        assert_eq!(book.best_ask().unwrap().price.0, 101.0);
     }
 
     #[tokio::test]
     async fn test_rate_limiter() {
-        let provider = CoinbaseExchange::new();
+        let stub = stub().await;
+        let provider = CoinbaseExchange::from_base_url(stub.base_url());
         // first request should pass
         assert!(provider.fetch_order_book(Product::BTCUSD).await.is_ok());
         // secong request should be rate limited
         let res = provider.fetch_order_book(Product::BTCUSD).await;
         assert!(matches!(res, Err(AggregatorError::RateLimitExceeded(_))));
+        // and never reach the exchange
+        assert_eq!(stub.coinbase_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_error_status() {
+        let stub = stub().await;
+        stub.set_coinbase(StubResponse::Status(503, "maintenance".to_string()));
+        let provider = CoinbaseExchange::from_base_url(stub.base_url());
+        let res = provider.fetch_order_book(Product::BTCUSD).await;
+        let Err(AggregatorError::Venue(error)) = res else {
+            panic!("expected a venue error, got {:?}", res);
+        };
+        assert_eq!(error.kind, VenueErrorKind::Unavailable);
+        assert_eq!(error.status, 503);
+        assert_eq!(error.message, "maintenance");
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_symbol() {
+        let stub = stub().await;
+        stub.set_coinbase(StubResponse::Status(
+            404,
+            r#"{"message":"NotFound"}"#.to_string(),
+        ));
+        let provider = CoinbaseExchange::from_base_url(stub.base_url());
+        let error = provider
+            .fetch_order_book(Product::BTCUSD)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnknownProduct);
+        assert!(!error.is_retryable());
+        assert_eq!(
+            error.to_string(),
+            "coinbase answered HTTP 404 (unknown_symbol): NotFound"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_body() {
+        let stub = stub().await;
+        stub.set_coinbase(StubResponse::Raw(r#"{"bids": "none"}"#.to_string()));
+        let provider = CoinbaseExchange::from_base_url(stub.base_url());
+        let error = provider
+            .fetch_order_book(Product::BTCUSD)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Parse);
     }
 }
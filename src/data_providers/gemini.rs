@@ -1,6 +1,6 @@
 use crate::{
-    data_providers::DataProvider,
-    error::AggregatorError,
+    data_providers::{DEFAULT_REQUEST_TIMEOUT, DataProvider, parse_body, retry_after},
+    error::{AggregatorError, VenueError, VenueErrorKind},
     order_book::OrderBook,
     rate_limiter::RateLimiter,
     types::{Exchange, Product},
@@ -8,14 +8,14 @@ use crate::{
 use async_trait::async_trait;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tokio::sync::Mutex;
-// Gemini API response structures
+// Gemini API response structures. Levels also carry a legacy timestamp in whole seconds of
+// when the level last changed, which does not date the snapshot and is ignored.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct GeminiPricelevel {
     price: String,
     amount: String,
-    timestamp: String,
 }
 
 // Gemini API response structure
@@ -25,26 +25,86 @@ struct GeminiBookResponse {
     asks: Vec<GeminiPricelevel>,
 }
 
+// Body of a Gemini error answer, e.g. {"result":"error","reason":"InvalidSymbol","message":...}
+#[derive(Debug, Clone, Deserialize)]
+struct GeminiErrorResponse {
+    reason: String,
+    #[serde(default)]
+    message: String,
+}
+
+// Classify by Gemini's reason code when the body has one, by status otherwise
+async fn error_response(response: reqwest::Response) -> AggregatorError {
+    let status = response.status().as_u16();
+    let retry_after = retry_after(response.headers());
+    let body = match response.text().await {
+        Ok(body) => body,
+        Err(error) => return error.into(),
+    };
+    let (code, message) = match serde_json::from_str::<GeminiErrorResponse>(&body) {
+        Ok(error) => (Some(error.reason), error.message),
+        Err(_) => (None, body),
+    };
+    let kind = match code.as_deref() {
+        Some("InvalidSymbol") => VenueErrorKind::UnknownSymbol,
+        Some("RateLimit" | "RateLimited") => VenueErrorKind::RateLimited,
+        Some("Maintenance" | "System") => VenueErrorKind::Unavailable,
+        Some("InvalidSignature" | "MissingApikeyHeader" | "InvalidNonce") => {
+            VenueErrorKind::Unauthorized
+        }
+        _ => VenueErrorKind::from_status(status),
+    };
+    VenueError {
+        venue: Exchange::Gemini,
+        kind,
+        status,
+        code,
+        message,
+        retry_after,
+    }
+    .into()
+}
+
 // Gemini Exchange Data Provider
 pub struct GeminiExchange {
     client: reqwest::Client,
     rate_limiter: Arc<Mutex<RateLimiter>>,
     base_url: Url,
+    request_timeout: Duration,
 }
 
 impl GeminiExchange {
+    // Client for the API base URL set in GEMINI_API_BASE_URL
     pub fn new() -> Self {
         let url =
             dotenvy::var("GEMINI_API_BASE_URL").expect("Failed to get gemini base url from env");
         let base_url = Url::parse(&url).expect("Invalid Gemini API base URL");
+        Self::from_base_url(base_url)
+    }
+
+    // Client for an explicit API base URL, e.g. a local stub
+    pub fn from_base_url(base_url: Url) -> Self {
         GeminiExchange {
             client: reqwest::Client::new(),
             rate_limiter: Arc::new(Mutex::new(
                 RateLimiter::new(1, 2), // 1 requests per 2 seconds.
             )),
             base_url,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
         }
     }
+
+    // Override the per-request HTTP timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+}
+
+impl Default for GeminiExchange {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // Implement DataProvider trait for GeminiExchange
@@ -66,17 +126,18 @@ impl DataProvider for GeminiExchange {
             .client
             .get(&url)
             .header("User-Agent", "order-book-aggregator/1.0")
+            .timeout(self.request_timeout)
             .send()
             .await?;
 
+        let status = response.status().as_u16();
+        tracing::Span::current().record("http_status", status);
+        tracing::debug!(http_status = status, %url, "venue responded");
         if !response.status().is_success() {
-            let err: String = response.text().await?;
-            return Err(AggregatorError::ExchangeError(format!(
-                "Failed to fetch order book from Gemini :  {}",
-                err
-            )));
+            return Err(error_response(response).await);
         }
-        let book: GeminiBookResponse = response.json().await?;
+        let book: GeminiBookResponse = parse_body(Exchange::Gemini, response).await?;
+        // No exchange timestamp, the age of the book is measured from its receive time
         let mut order_book = OrderBook::new(Exchange::Gemini);
         // Add bids to order book
         for level in &book.bids {
@@ -102,21 +163,79 @@ impl DataProvider for GeminiExchange {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        data_providers::stub::{ExchangeStub, StubResponse},
+        error::ErrorKind,
+    };
+
+    async fn stub() -> ExchangeStub {
+        let mut book = OrderBook::new(Exchange::Gemini);
+        book.add_bid(100.0, 1.5);
+        book.add_ask(101.0, 0.25);
+        book.add_ask(101.5, 3.0);
+        book.exchange_timestamp_ms = Some(1_700_000_000_000);
+        ExchangeStub::start(OrderBook::new(Exchange::Coinbase), book)
+            .await
+            .unwrap()
+    }
 
     #[tokio::test]
     async fn test_fetch_gemini_order_book() {
-        let exchange = GeminiExchange::new();
+        let stub = stub().await;
+        let exchange = GeminiExchange::from_base_url(stub.base_url());
         let order_book = exchange.fetch_order_book(Product::BTCUSD).await.unwrap();
-        assert!(!order_book.is_empty());
+        assert_eq!(order_book.exchange, Exchange::Gemini);
+        // Level timestamps do not date the book
+        assert_eq!(order_book.exchange_timestamp_ms, None);
+        assert_eq!(order_book.asks.len(), 2);
+        assert_eq!(order_book.best_bid().unwrap().price.0, 100.0);
     }
 
     #[tokio::test]
     async fn test_rate_limiter() {
-        let provider = GeminiExchange::new();
+        let stub = stub().await;
+        let provider = GeminiExchange::from_base_url(stub.base_url());
         // first request should pass
         assert!(provider.fetch_order_book(Product::BTCUSD).await.is_ok());
         // secong request should be rate limited
         let res = provider.fetch_order_book(Product::BTCUSD).await;
         assert!(matches!(res, Err(AggregatorError::RateLimitExceeded(_))));
+        assert_eq!(stub.gemini_requests(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_error_status() {
+        let stub = stub().await;
+        stub.set_gemini(StubResponse::Status(429, "Too many requests".to_string()));
+        let provider = GeminiExchange::from_base_url(stub.base_url());
+        let res = provider.fetch_order_book(Product::BTCUSD).await;
+        let Err(AggregatorError::Venue(error)) = res else {
+            panic!("expected a venue error, got {:?}", res);
+        };
+        assert_eq!(error.kind, VenueErrorKind::RateLimited);
+        assert_eq!(error.message, "Too many requests");
+        assert!(error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_symbol() {
+        let stub = stub().await;
+        stub.set_gemini(StubResponse::Status(
+            400,
+            r#"{"result":"error","reason":"InvalidSymbol","message":"Supplied value 'xyz' is not a valid symbol"}"#.to_string(),
+        ));
+        let provider = GeminiExchange::from_base_url(stub.base_url());
+        let error = provider
+            .fetch_order_book(Product::BTCUSD)
+            .await
+            .unwrap_err();
+        let AggregatorError::Venue(venue_error) = &error else {
+            panic!("expected a venue error, got {:?}", error);
+        };
+        assert_eq!(venue_error.kind, VenueErrorKind::UnknownSymbol);
+        assert_eq!(venue_error.status, 400);
+        assert_eq!(venue_error.code.as_deref(), Some("InvalidSymbol"));
+        assert_eq!(error.kind(), ErrorKind::UnknownProduct);
+        assert!(!error.is_retryable());
     }
 }
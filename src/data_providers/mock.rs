@@ -0,0 +1,130 @@
+use crate::{
+    data_providers::DataProvider, error::AggregatorError, order_book::OrderBook, types::Product,
+};
+use async_trait::async_trait;
+use std::{
+    collections::VecDeque,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+// Configurable in-process venue for tests and simulations.
+// Answers every fetch with a copy of its current book after an optional delay, unless an
+// injected error is pending, in which case the oldest pending error is returned instead.
+pub struct MockProvider {
+    name: String,
+    book: Mutex<OrderBook>,
+    latency: Duration,
+    errors: Mutex<VecDeque<AggregatorError>>,
+    fetches: AtomicUsize,
+}
+
+impl MockProvider {
+    pub fn new(name: impl Into<String>, book: OrderBook) -> Self {
+        MockProvider {
+            name: name.into(),
+            book: Mutex::new(book),
+            latency: Duration::ZERO,
+            errors: Mutex::new(VecDeque::new()),
+            fetches: AtomicUsize::new(0),
+        }
+    }
+
+    // Delay every answer, successful or not, by `latency`
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    // Fail the next fetch that has no earlier error pending with `error`
+    pub fn with_error(self, error: AggregatorError) -> Self {
+        self.fail_next(error);
+        self
+    }
+
+    pub fn fail_next(&self, error: AggregatorError) {
+        self.errors
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push_back(error);
+    }
+
+    // Replace the book served by the following fetches
+    pub fn set_book(&self, book: OrderBook) {
+        *self
+            .book
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = book;
+    }
+
+    // Change the served book in place
+    pub fn update_book(&self, update: impl FnOnce(&mut OrderBook)) {
+        update(
+            &mut self
+                .book
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+    }
+
+    // Number of fetches started so far
+    pub fn fetches(&self) -> usize {
+        self.fetches.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl DataProvider for MockProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn fetch_order_book(&self, _: Product) -> Result<OrderBook, AggregatorError> {
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        let error = self
+            .errors
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .pop_front();
+        match error {
+            Some(error) => Err(error),
+            None => Ok(self
+                .book
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Exchange;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn test_errors_then_book() {
+        let mut book = OrderBook::new(Exchange::Gemini);
+        book.add_bid(99.0, 1.0);
+        let provider = MockProvider::new("Gemini", book)
+            .with_latency(Duration::from_millis(20))
+            .with_error(AggregatorError::RateLimitExceeded("slow down".to_string()));
+
+        let started = Instant::now();
+        let res = provider.fetch_order_book(Product::BTCUSD).await;
+        assert!(matches!(res, Err(AggregatorError::RateLimitExceeded(_))));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+
+        provider.update_book(|book| book.add_bid(98.0, 1.0));
+        let book = provider.fetch_order_book(Product::BTCUSD).await.unwrap();
+        assert_eq!(book.bids.len(), 2);
+        assert_eq!(provider.fetches(), 2);
+    }
+}
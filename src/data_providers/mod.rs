@@ -1,10 +1,63 @@
-use crate::{error::AggregatorError, order_book::OrderBook, types::Product};
+use crate::{
+    error::{AggregatorError, VenueError, VenueErrorKind},
+    order_book::OrderBook,
+    types::{Exchange, Product, unix_time_ms},
+};
 use async_trait::async_trait;
+use reqwest::{Response, header::HeaderMap};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
 pub mod coinbase;
 pub mod gemini;
+pub mod mock;
+pub mod replay;
+#[cfg(any(test, feature = "test-util"))]
+pub mod stub;
+
+// Default timeout applied to every exchange HTTP request
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Seconds form of a Retry-After header, the HTTP date form is ignored
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+// Payload of a successful answer. A body that does not parse is reported as an
+// InvalidResponse from `venue`, not as a transport error.
+pub async fn parse_body<T: DeserializeOwned>(
+    venue: Exchange,
+    response: Response,
+) -> Result<T, AggregatorError> {
+    let status = response.status().as_u16();
+    let body = response.bytes().await?;
+    serde_json::from_slice(&body).map_err(|error| {
+        VenueError {
+            venue,
+            kind: VenueErrorKind::InvalidResponse,
+            status,
+            code: None,
+            message: error.to_string(),
+            retry_after: None,
+        }
+        .into()
+    })
+}
 
 #[async_trait]
 pub trait DataProvider: Send + Sync {
     fn name(&self) -> &str;
     async fn fetch_order_book(&self, product_id: Product) -> Result<OrderBook, AggregatorError>;
+
+    // Current time in unix milliseconds as seen by the provider, the age of its books is
+    // measured against it. Wall clock time unless the provider replays recorded books.
+    fn now_ms(&self) -> u64 {
+        unix_time_ms()
+    }
 }
@@ -0,0 +1,254 @@
+use crate::{
+    data_providers::DataProvider,
+    error::AggregatorError,
+    order_book::OrderBook,
+    recorder::{Record, RecordReader},
+    types::{Product, unix_time_ms},
+};
+use async_trait::async_trait;
+use std::{
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+// Clock shared by replay providers and the code driving a replay, in unix milliseconds
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedClock(Arc<AtomicU64>);
+
+impl SimulatedClock {
+    pub fn new(start_ms: u64) -> Self {
+        SimulatedClock(Arc::new(AtomicU64::new(start_ms)))
+    }
+
+    pub fn now_ms(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn set_ms(&self, now_ms: u64) {
+        self.0.store(now_ms, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.0.fetch_add(by.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+// How a ReplayProvider picks the record served by a fetch
+#[derive(Debug, Clone)]
+pub enum ReplayMode {
+    // Every fetch serves the next record, in recording order
+    Sequential,
+    // Every fetch serves the latest record recorded at or before the clock
+    SimulatedTime(SimulatedClock),
+}
+
+// Serves the recorded fetches of one venue instead of calling the exchange.
+// Recorded failures are replayed as ExchangeError with the recorded message.
+pub struct ReplayProvider {
+    name: String,
+    // Records of this venue, ordered by recorded_at_ms
+    records: Vec<Record>,
+    mode: ReplayMode,
+    // Index of the next record in sequential mode
+    cursor: Mutex<usize>,
+    // Recording time of the record served last in sequential mode, 0 before the first fetch
+    replayed_at_ms: AtomicU64,
+}
+
+impl ReplayProvider {
+    // Replay the records of `venue` (matched case-insensitively) from `records`, sequentially
+    pub fn new(venue: &str, records: impl IntoIterator<Item = Record>) -> Self {
+        let mut records: Vec<Record> = records
+            .into_iter()
+            .filter(|record| record.venue.eq_ignore_ascii_case(venue))
+            .collect();
+        // Stable, so records with equal timestamps keep their recording order
+        records.sort_by_key(|record| record.recorded_at_ms);
+        let name = records
+            .first()
+            .map_or_else(|| venue.to_string(), |record| record.venue.clone());
+        ReplayProvider {
+            name,
+            records,
+            mode: ReplayMode::Sequential,
+            cursor: Mutex::new(0),
+            replayed_at_ms: AtomicU64::new(0),
+        }
+    }
+
+    // Replay the records of `venue` from a file written by Recorder
+    pub fn open(path: impl AsRef<Path>, venue: &str) -> Result<Self, AggregatorError> {
+        let records = RecordReader::open(path)?.collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(venue, records))
+    }
+
+    // Serve records by simulated time instead of sequentially
+    pub fn with_clock(mut self, clock: SimulatedClock) -> Self {
+        self.mode = ReplayMode::SimulatedTime(clock);
+        self
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    fn next_sequential(&self, product: &str) -> Option<&Record> {
+        let mut cursor = self
+            .cursor
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let offset = self.records[*cursor..]
+            .iter()
+            .position(|record| record.product == product)?;
+        let record = &self.records[*cursor + offset];
+        *cursor += offset + 1;
+        self.replayed_at_ms
+            .store(record.recorded_at_ms, Ordering::SeqCst);
+        Some(record)
+    }
+
+    fn at_time(&self, product: &str, now_ms: u64) -> Option<&Record> {
+        self.records
+            .iter()
+            .take_while(|record| record.recorded_at_ms <= now_ms)
+            .filter(|record| record.product == product)
+            .last()
+    }
+}
+
+#[async_trait]
+impl DataProvider for ReplayProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    // The simulated clock, or in sequential mode the time the last served record was recorded,
+    // so replayed books are as old as they were when recorded
+    fn now_ms(&self) -> u64 {
+        match &self.mode {
+            ReplayMode::SimulatedTime(clock) => clock.now_ms(),
+            ReplayMode::Sequential => match self.replayed_at_ms.load(Ordering::SeqCst) {
+                0 => unix_time_ms(),
+                replayed_at_ms => replayed_at_ms,
+            },
+        }
+    }
+
+    async fn fetch_order_book(&self, product_id: Product) -> Result<OrderBook, AggregatorError> {
+        let product = product_id.to_string();
+        let record = match &self.mode {
+            ReplayMode::Sequential => self.next_sequential(&product).ok_or_else(|| {
+                AggregatorError::ExchangeError(format!(
+                    "Replay of {} {} is exhausted",
+                    self.name, product
+                ))
+            })?,
+            ReplayMode::SimulatedTime(clock) => {
+                let now_ms = clock.now_ms();
+                self.at_time(&product, now_ms).ok_or_else(|| {
+                    AggregatorError::ExchangeError(format!(
+                        "No {} {} book recorded at or before {}",
+                        self.name, product, now_ms
+                    ))
+                })?
+            }
+        };
+        match (&record.book, &record.error) {
+            (Some(book), _) => Ok(book.clone()),
+            (None, error) => Err(AggregatorError::ExchangeError(
+                error.clone().unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{recorder::Recorder, types::Exchange};
+
+    fn record(venue: &str, recorded_at_ms: u64, bid: Option<f64>) -> Record {
+        let book = bid.map(|price| {
+            let mut book = OrderBook::new(Exchange::Coinbase);
+            book.add_bid(price, 1.0);
+            book
+        });
+        Record {
+            venue: venue.to_string(),
+            product: "BTC-USD".to_string(),
+            recorded_at_ms,
+            latency_ms: 0,
+            error: book.is_none().then(|| "maintenance".to_string()),
+            book,
+        }
+    }
+
+    fn best_bid(book: OrderBook) -> f64 {
+        book.best_bid().unwrap().price.0
+    }
+
+    #[tokio::test]
+    async fn test_sequential_replay() {
+        let provider = ReplayProvider::new(
+            "coinbase",
+            vec![
+                record("Coinbase", 1, Some(99.0)),
+                record("Gemini", 2, Some(50.0)),
+                record("Coinbase", 3, None),
+                record("Coinbase", 4, Some(98.0)),
+            ],
+        );
+        assert_eq!(provider.name(), "Coinbase");
+        let fetch = || provider.fetch_order_book(Product::BTCUSD);
+        assert_eq!(best_bid(fetch().await.unwrap()), 99.0);
+        assert!(matches!(
+            fetch().await,
+            Err(AggregatorError::ExchangeError(message)) if message == "maintenance"
+        ));
+        assert_eq!(best_bid(fetch().await.unwrap()), 98.0);
+        assert!(fetch().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_simulated_time_replay() {
+        let clock = SimulatedClock::new(0);
+        let provider = ReplayProvider::new(
+            "Coinbase",
+            vec![
+                record("Coinbase", 2_000, Some(98.0)),
+                record("Coinbase", 1_000, Some(99.0)),
+            ],
+        )
+        .with_clock(clock.clone());
+        assert!(provider.fetch_order_book(Product::BTCUSD).await.is_err());
+
+        clock.set_ms(1_500);
+        let book = provider.fetch_order_book(Product::BTCUSD).await.unwrap();
+        assert_eq!(best_bid(book), 99.0);
+        // The same book is served until the clock reaches the next record
+        let book = provider.fetch_order_book(Product::BTCUSD).await.unwrap();
+        assert_eq!(best_bid(book), 99.0);
+
+        clock.advance(Duration::from_secs(1));
+        let book = provider.fetch_order_book(Product::BTCUSD).await.unwrap();
+        assert_eq!(best_bid(book), 98.0);
+    }
+
+    #[tokio::test]
+    async fn test_open_recording() {
+        let path = std::env::temp_dir().join(format!("replay-{}.gz", std::process::id()));
+        let recorder = Recorder::open(&path).unwrap();
+        recorder.append(&record("Gemini", 1, Some(97.0))).unwrap();
+        drop(recorder);
+
+        let provider = ReplayProvider::open(&path, "gemini").unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(provider.records().len(), 1);
+        let book = provider.fetch_order_book(Product::BTCUSD).await.unwrap();
+        assert_eq!(best_bid(book), 97.0);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    error::AggregatorError,
+    order_book::{Level, OrderBook},
+    types::Product,
+};
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use reqwest::Url;
+use serde_json::{Value, json};
+use std::{
+    str::FromStr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+// What the stub answers on a venue's book endpoint
+#[derive(Debug, Clone)]
+pub enum StubResponse {
+    // The book in the venue's JSON format
+    Book(OrderBook),
+    // This status code with this body, e.g. 429 or 503 with the venue's error message
+    Status(u16, String),
+    // Status 200 with this body served verbatim, for malformed payloads
+    Raw(String),
+}
+
+#[derive(Debug)]
+struct StubVenue {
+    response: Mutex<StubResponse>,
+    requests: AtomicUsize,
+}
+
+impl StubVenue {
+    fn new(book: OrderBook) -> Self {
+        StubVenue {
+            response: Mutex::new(StubResponse::Book(book)),
+            requests: AtomicUsize::new(0),
+        }
+    }
+
+    fn next_response(&self) -> StubResponse {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        self.response
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+}
+
+#[derive(Debug)]
+struct StubState {
+    coinbase: StubVenue,
+    gemini: StubVenue,
+}
+
+// Local HTTP server mimicking the Coinbase and Gemini order book endpoints, so the exchange
+// clients can be exercised offline. Point them at `base_url()` for both venues.
+pub struct ExchangeStub {
+    base_url: Url,
+    state: Arc<StubState>,
+    task: JoinHandle<()>,
+}
+
+impl ExchangeStub {
+    // Start on an ephemeral local port, both venues serving the given books
+    pub async fn start(coinbase: OrderBook, gemini: OrderBook) -> Result<Self, AggregatorError> {
+        let state = Arc::new(StubState {
+            coinbase: StubVenue::new(coinbase),
+            gemini: StubVenue::new(gemini),
+        });
+        let router = Router::new()
+            .route("/products/{symbol}/book", get(coinbase_book))
+            .route("/v1/book/{symbol}", get(gemini_book))
+            .with_state(Arc::clone(&state));
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let base_url = Url::parse(&format!("http://{}/", listener.local_addr()?))
+            .map_err(|error| AggregatorError::ExchangeError(error.to_string()))?;
+        let task = tokio::spawn(async move {
+            let _ = axum::serve(listener, router).await;
+        });
+        Ok(ExchangeStub {
+            base_url,
+            state,
+            task,
+        })
+    }
+
+    pub fn base_url(&self) -> Url {
+        self.base_url.clone()
+    }
+
+    pub fn set_coinbase(&self, response: StubResponse) {
+        *self
+            .state
+            .coinbase
+            .response
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = response;
+    }
+
+    pub fn set_gemini(&self, response: StubResponse) {
+        *self
+            .state
+            .gemini
+            .response
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = response;
+    }
+
+    // Requests received on the Coinbase book endpoint
+    pub fn coinbase_requests(&self) -> usize {
+        self.state.coinbase.requests.load(Ordering::SeqCst)
+    }
+
+    // Requests received on the Gemini book endpoint
+    pub fn gemini_requests(&self) -> usize {
+        self.state.gemini.requests.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for ExchangeStub {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn respond(response: StubResponse, render: impl Fn(&OrderBook) -> Value) -> Response {
+    match response {
+        StubResponse::Book(book) => Json(render(&book)).into_response(),
+        StubResponse::Status(status, body) => (
+            StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            body,
+        )
+            .into_response(),
+        StubResponse::Raw(body) => body.into_response(),
+    }
+}
+
+// GET /products/{symbol}/book?level=2
+async fn coinbase_book(
+    State(state): State<Arc<StubState>>,
+    Path(symbol): Path<String>,
+) -> Response {
+    if Product::from_str(&symbol).is_err() {
+        return (StatusCode::NOT_FOUND, Json(json!({"message": "NotFound"}))).into_response();
+    }
+    respond(state.coinbase.next_response(), |book| {
+        // [price, size, number of orders]
+        let level = |level: &Level| json!([level.price.to_string(), level.quantity.to_string(), 1]);
+        json!({
+            "bids": book.bids.values().rev().map(level).collect::<Vec<_>>(),
+            "asks": book.asks.values().map(level).collect::<Vec<_>>(),
+            "sequence": book.sequence,
+        })
+    })
+}
+
+// GET /v1/book/{symbol}
+async fn gemini_book(State(state): State<Arc<StubState>>, Path(symbol): Path<String>) -> Response {
+    if Product::from_str(&symbol).is_err() {
+        let error = json!({"result": "error", "reason": "InvalidSymbol", "message": symbol});
+        return (StatusCode::BAD_REQUEST, Json(error)).into_response();
+    }
+    respond(state.gemini.next_response(), |book| {
+        // Gemini timestamps every level in seconds
+        let timestamp = book.exchange_timestamp_ms.unwrap_or(book.received_at_ms) / 1000;
+        let level = |level: &Level| {
+            json!({
+                "price": level.price.to_string(),
+                "amount": level.quantity.to_string(),
+                "timestamp": timestamp.to_string(),
+            })
+        };
+        json!({
+            "bids": book.bids.values().rev().map(level).collect::<Vec<_>>(),
+            "asks": book.asks.values().map(level).collect::<Vec<_>>(),
+        })
+    })
+}
@@ -1,3 +1,5 @@
+use crate::{aggregator::VenueReport, types::Exchange};
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -5,9 +7,15 @@ pub enum AggregatorError {
     /// Insufficient liquidity error
     #[error("{0}")]
     InsufficientLiquidity(String),
-    /// Aggregation Failed
+    /// Aggregation Failed, with the outcome of every venue of the round
     #[error("Failed to aggregate order books")]
-    AggregationFailed,
+    AggregationFailed { venues: Vec<VenueReport> },
+    /// Aggregation policy not satisfied, with the outcome of every venue of the round
+    #[error("Aggregation policy not met: {violation}")]
+    PolicyNotMet {
+        violation: PolicyViolation,
+        venues: Vec<VenueReport>,
+    },
     /// Rate limiter error
     #[error("{0}")]
     RateLimitExceeded(String),
@@ -23,7 +31,245 @@ pub enum AggregatorError {
     /// Exchange error.
     #[error("{0}")]
     ExchangeError(String),
+    /// Error answer of a venue API, classified by status and error code
+    #[error(transparent)]
+    Venue(#[from] VenueError),
     /// Environment variable error
     #[error(transparent)]
     DotenvyError(#[from] dotenvy::Error),
+    /// Product symbol not supported
+    #[error("Unknown product {0}")]
+    UnknownProduct(String),
+    /// Venue name not supported
+    #[error("Unknown exchange {0}")]
+    UnknownExchange(String),
+    /// Price bucket width that is neither a positive price step nor a positive bp width
+    #[error("Invalid bucket width {0}, expected a price step such as 10 or a width such as 1bp")]
+    InvalidBucketWidth(String),
+    /// Log filter directive tracing-subscriber could not parse
+    #[error("Invalid log level: {0}")]
+    InvalidLogFilter(String),
+    /// Backtest asked for a book the recording does not hold
+    #[error("No recorded book {0}")]
+    NoRecordedBook(String),
+    /// Execution schedule that cannot be planned, e.g. no slices or a zero quantity
+    #[error("Invalid schedule: {0}")]
+    InvalidSchedule(String),
+    /// I/O error
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+// What went wrong on the venue side, decides whether a request is worth repeating
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VenueErrorKind {
+    // The venue does not list the requested product
+    UnknownSymbol,
+    // The venue rejected the request for exceeding its rate limit
+    RateLimited,
+    // Maintenance or overload, typically a 503
+    Unavailable,
+    // Credentials missing or refused
+    Unauthorized,
+    // Any other 4xx, the request itself is wrong
+    BadRequest,
+    // Any other 5xx
+    ServerError,
+    // A success status with a body that is not a book
+    InvalidResponse,
+}
+
+impl VenueErrorKind {
+    // Kind implied by the HTTP status alone, for venues without a more specific error code
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 | 403 => VenueErrorKind::Unauthorized,
+            404 => VenueErrorKind::UnknownSymbol,
+            429 => VenueErrorKind::RateLimited,
+            503 => VenueErrorKind::Unavailable,
+            500..=599 => VenueErrorKind::ServerError,
+            _ => VenueErrorKind::BadRequest,
+        }
+    }
+
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            VenueErrorKind::RateLimited | VenueErrorKind::Unavailable | VenueErrorKind::ServerError
+        )
+    }
+}
+
+impl std::fmt::Display for VenueErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            VenueErrorKind::UnknownSymbol => "unknown_symbol",
+            VenueErrorKind::RateLimited => "rate_limited",
+            VenueErrorKind::Unavailable => "unavailable",
+            VenueErrorKind::Unauthorized => "unauthorized",
+            VenueErrorKind::BadRequest => "bad_request",
+            VenueErrorKind::ServerError => "server_error",
+            VenueErrorKind::InvalidResponse => "invalid_response",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+// Error answer of a venue API
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VenueError {
+    pub venue: Exchange,
+    pub kind: VenueErrorKind,
+    pub status: u16,
+    // Error code or reason given by the venue, e.g. InvalidSymbol on Gemini
+    pub code: Option<String>,
+    pub message: String,
+    // Wait requested by the venue in a Retry-After header
+    pub retry_after: Option<Duration>,
+}
+
+impl VenueError {
+    pub fn is_retryable(&self) -> bool {
+        self.kind.is_retryable()
+    }
+}
+
+impl std::fmt::Display for VenueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} answered HTTP {} ({}",
+            self.venue, self.status, self.kind
+        )?;
+        if let Some(code) = &self.code {
+            write!(f, ", {}", code)?;
+        }
+        write!(f, "): {}", self.message)
+    }
+}
+
+impl std::error::Error for VenueError {}
+
+// Reason an aggregation was rejected by the configured AggregationPolicy
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PolicyViolation {
+    #[error("{available} venue(s) answered, at least {required} required")]
+    TooFewVenues { required: usize, available: usize },
+    #[error("required venue {0} did not answer")]
+    MissingVenue(String),
+    #[error(
+        "consolidated book has {bid_levels} bid and {ask_levels} ask level(s), at least {required} required per side"
+    )]
+    InsufficientDepth {
+        required: usize,
+        bid_levels: usize,
+        ask_levels: usize,
+    },
+}
+
+// Coarse classification of an AggregatorError, cheap to copy into reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    InsufficientLiquidity,
+    AggregationFailed,
+    PolicyNotMet,
+    RateLimited,
+    Timeout,
+    Stale,
+    Network,
+    Parse,
+    Exchange,
+    Config,
+    UnknownProduct,
+    // Venue answered 503 or reported maintenance
+    Unavailable,
+    // Venue answered with another 5xx
+    ServerError,
+    // Venue refused the credentials
+    Unauthorized,
+    // Venue rejected the request as malformed
+    BadRequest,
+    // Venue skipped because its circuit breaker is open
+    CircuitOpen,
+    // Nothing recorded to backtest against
+    NoRecordedBook,
+    Internal,
+}
+
+impl AggregatorError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            AggregatorError::InsufficientLiquidity(_) => ErrorKind::InsufficientLiquidity,
+            AggregatorError::AggregationFailed { .. } => ErrorKind::AggregationFailed,
+            AggregatorError::PolicyNotMet { .. } => ErrorKind::PolicyNotMet,
+            AggregatorError::RateLimitExceeded(_) => ErrorKind::RateLimited,
+            AggregatorError::Json(_) | AggregatorError::ParseFloatError(_) => ErrorKind::Parse,
+            AggregatorError::Reqwest(error) if error.is_timeout() => ErrorKind::Timeout,
+            AggregatorError::Reqwest(error) if error.is_decode() => ErrorKind::Parse,
+            AggregatorError::Reqwest(_) => ErrorKind::Network,
+            AggregatorError::ExchangeError(_) => ErrorKind::Exchange,
+            AggregatorError::Venue(error) => match error.kind {
+                VenueErrorKind::UnknownSymbol => ErrorKind::UnknownProduct,
+                VenueErrorKind::RateLimited => ErrorKind::RateLimited,
+                VenueErrorKind::Unavailable => ErrorKind::Unavailable,
+                VenueErrorKind::Unauthorized => ErrorKind::Unauthorized,
+                VenueErrorKind::BadRequest => ErrorKind::BadRequest,
+                VenueErrorKind::ServerError => ErrorKind::ServerError,
+                VenueErrorKind::InvalidResponse => ErrorKind::Parse,
+            },
+            AggregatorError::DotenvyError(_) => ErrorKind::Config,
+            AggregatorError::UnknownProduct(_) => ErrorKind::UnknownProduct,
+            AggregatorError::NoRecordedBook(_) => ErrorKind::NoRecordedBook,
+            AggregatorError::UnknownExchange(_)
+            | AggregatorError::InvalidBucketWidth(_)
+            | AggregatorError::InvalidLogFilter(_)
+            | AggregatorError::InvalidSchedule(_) => ErrorKind::Config,
+            AggregatorError::Io(_) => ErrorKind::Internal,
+        }
+    }
+
+    // Venue reports of a failed aggregation round
+    pub fn venue_reports(&self) -> Option<&[VenueReport]> {
+        match self {
+            AggregatorError::AggregationFailed { venues }
+            | AggregatorError::PolicyNotMet { venues, .. } => Some(venues),
+            _ => None,
+        }
+    }
+
+    // Whether repeating the request later may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AggregatorError::Venue(error) => error.is_retryable(),
+            AggregatorError::RateLimitExceeded(_) => true,
+            AggregatorError::Reqwest(error) => error.is_timeout() || error.is_connect(),
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            ErrorKind::InsufficientLiquidity => "insufficient_liquidity",
+            ErrorKind::AggregationFailed => "aggregation_failed",
+            ErrorKind::PolicyNotMet => "policy_not_met",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Stale => "stale",
+            ErrorKind::Network => "network",
+            ErrorKind::Parse => "parse",
+            ErrorKind::Exchange => "exchange",
+            ErrorKind::Config => "config",
+            ErrorKind::UnknownProduct => "unknown_product",
+            ErrorKind::Unavailable => "unavailable",
+            ErrorKind::ServerError => "server_error",
+            ErrorKind::Unauthorized => "unauthorized",
+            ErrorKind::BadRequest => "bad_request",
+            ErrorKind::CircuitOpen => "circuit_open",
+            ErrorKind::NoRecordedBook => "no_recorded_book",
+            ErrorKind::Internal => "internal",
+        };
+        write!(f, "{}", kind)
+    }
 }
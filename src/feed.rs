@@ -0,0 +1,153 @@
+use crate::{
+    aggregator::{AggregationReport, OrderBookAggregator, VenueReport},
+    health::VenueCircuit,
+    order_book::OrderBook,
+    types::{Product, unix_time_ms},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::{MissedTickBehavior, interval},
+};
+
+// Default pause between two aggregation rounds, above the 1 request per 2 seconds venue limits
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(3);
+// Venue books buffered by `venue_books` before it waits on its receiver
+const VENUE_BOOK_BUFFER: usize = 16;
+
+// Latest state published by a BookFeed
+#[derive(Debug, Clone, Default)]
+pub struct FeedState {
+    // Most recent successful aggregation
+    pub report: Option<Arc<AggregationReport>>,
+    // Successful aggregation before `report`, the base of its order flow
+    pub previous: Option<Arc<AggregationReport>>,
+    // Outcome of every venue in the most recent round, failed rounds included
+    pub venues: Vec<VenueReport>,
+    // Error of the most recent round, if it failed
+    pub last_error: Option<String>,
+    // Unix milliseconds at which the most recent round finished
+    pub updated_at_ms: u64,
+    // Unix milliseconds at which `report` was aggregated, None before the first success
+    pub succeeded_at_ms: Option<u64>,
+    // Circuit breaker state of every venue after the most recent round
+    pub health: Vec<VenueCircuit>,
+}
+
+impl FeedState {
+    // Age of `report` at `now_ms`, None before the first successful round
+    pub fn report_age_ms(&self, now_ms: u64) -> Option<u64> {
+        self.succeeded_at_ms
+            .map(|succeeded_at_ms| now_ms.saturating_sub(succeeded_at_ms))
+    }
+}
+
+// Keeps an aggregator running in the background and publishes every round
+pub struct BookFeed {
+    product: Product,
+    state: watch::Receiver<FeedState>,
+    task: JoinHandle<()>,
+}
+
+impl BookFeed {
+    // Start refreshing `aggregator` every `refresh_interval`
+    pub fn spawn(aggregator: OrderBookAggregator, refresh_interval: Duration) -> Self {
+        let product = aggregator.product_id().clone();
+        let (sender, state) = watch::channel(FeedState::default());
+        let task = tokio::spawn(async move {
+            let mut ticker = interval(refresh_interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let result = aggregator.fetch_and_aggregate_data().await;
+                let health = aggregator.venue_health();
+                let now_ms = unix_time_ms();
+                sender.send_modify(|state| {
+                    match result {
+                        Ok(report) => {
+                            state.venues = report.venues.clone();
+                            state.previous = state.report.replace(Arc::new(report));
+                            state.last_error = None;
+                            state.succeeded_at_ms = Some(now_ms);
+                        }
+                        // The last good report stays available, its age tells how old it is
+                        Err(error) => {
+                            state.venues = error.venue_reports().unwrap_or_default().to_vec();
+                            state.last_error = Some(error.to_string());
+                        }
+                    }
+                    state.updated_at_ms = now_ms;
+                    state.health = health;
+                });
+            }
+        });
+        BookFeed {
+            product,
+            state,
+            task,
+        }
+    }
+
+    pub fn product(&self) -> &Product {
+        &self.product
+    }
+
+    // Snapshot of the current state
+    pub fn state(&self) -> FeedState {
+        self.state.borrow().clone()
+    }
+
+    // Most recent successful aggregation, if any round succeeded yet
+    pub fn latest(&self) -> Option<Arc<AggregationReport>> {
+        self.state.borrow().report.clone()
+    }
+
+    // Most recent successful aggregation together with the one before it, read at once so
+    // the two are consecutive
+    pub fn latest_with_previous(
+        &self,
+    ) -> Option<(Arc<AggregationReport>, Option<Arc<AggregationReport>>)> {
+        let state = self.state.borrow();
+        let report = state.report.clone()?;
+        Some((report, state.previous.clone()))
+    }
+
+    // Receiver notified after every aggregation round
+    pub fn subscribe(&self) -> watch::Receiver<FeedState> {
+        self.state.clone()
+    }
+
+    // Stream of the venue books of every successful round, e.g. for ArbitrageScanner::run.
+    // Failed rounds send nothing, and the stream ends with the feed or its receiver.
+    pub fn venue_books(&self) -> mpsc::Receiver<Arc<OrderBook>> {
+        let mut updates = self.subscribe();
+        let (sender, receiver) = mpsc::channel(VENUE_BOOK_BUFFER);
+        tokio::spawn(async move {
+            // Start with the books of the current round, if there is one
+            updates.mark_changed();
+            let mut sent: Option<Arc<AggregationReport>> = None;
+            while updates.changed().await.is_ok() {
+                let Some(report) = updates.borrow_and_update().report.clone() else {
+                    continue;
+                };
+                if sent.as_ref().is_some_and(|sent| Arc::ptr_eq(sent, &report)) {
+                    continue;
+                }
+                for (_, book) in &report.venue_books {
+                    if sender.send(Arc::clone(book)).await.is_err() {
+                        return;
+                    }
+                }
+                sent = Some(report);
+            }
+        });
+        receiver
+    }
+}
+
+impl Drop for BookFeed {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
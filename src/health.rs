@@ -0,0 +1,292 @@
+use crate::{
+    aggregator::{VenueReport, VenueStatus},
+    error::ErrorKind,
+    types::unix_time_ms,
+};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+// Consecutive failed fetches that open the circuit of a venue
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+// Time an open circuit skips its venue before letting a probe through
+pub const DEFAULT_OPEN_DURATION: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    pub failure_threshold: u32,
+    pub open_duration: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            open_duration: DEFAULT_OPEN_DURATION,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CircuitState {
+    // Venue is queried every round
+    #[default]
+    Closed,
+    // Venue failed too often and is skipped until the open duration elapses
+    Open,
+    // Open duration elapsed, a single probe decides between closing and reopening
+    HalfOpen,
+}
+
+impl std::fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half_open"),
+        }
+    }
+}
+
+// Health of one venue as tracked by a CircuitBreaker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VenueCircuit {
+    pub venue: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    // Kind of the most recent failure, cleared by a successful fetch
+    pub last_error: Option<ErrorKind>,
+    // Unix milliseconds after which an open circuit lets a probe through
+    pub retry_at_ms: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    last_error: Option<ErrorKind>,
+    opened_at: Option<Instant>,
+    // Start of the probe of a half open circuit, until its fetch is recorded
+    probe_started: Option<Instant>,
+}
+
+// Per venue circuit breakers, keyed by provider name. A venue whose fetches fail
+// `failure_threshold` times in a row is skipped for `open_duration`, then a single fetch
+// probes it again: an answer closes the circuit, another failure reopens it.
+// Only failures pointing at the venue itself count, see `is_venue_failure`.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            config,
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Whether `venue` should be fetched now. Once the open duration has elapsed the circuit
+    // half opens and lets one probe through; a probe whose fetch was never recorded, e.g.
+    // because its round was dropped, is given up after another open duration.
+    pub fn allow(&self, venue: &str) -> bool {
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(circuit) = circuits.get_mut(venue) else {
+            return true;
+        };
+        let open_duration = self.config.open_duration;
+        let allowed = match circuit.state {
+            CircuitState::Closed => return true,
+            CircuitState::Open => circuit
+                .opened_at
+                .is_some_and(|opened_at| opened_at.elapsed() >= open_duration),
+            CircuitState::HalfOpen => circuit
+                .probe_started
+                .is_none_or(|started| started.elapsed() >= open_duration),
+        };
+        if allowed {
+            circuit.state = CircuitState::HalfOpen;
+            circuit.probe_started = Some(Instant::now());
+        }
+        allowed
+    }
+
+    // Update the circuit of a fetched venue. Any answer counts as a success, including a
+    // stale book. Skipped venues and failures that are not the venue's fault leave the
+    // circuit as it is.
+    pub fn record(&self, report: &VenueReport) {
+        if report.status == VenueStatus::Skipped {
+            return;
+        }
+        let mut circuits = self.circuits.lock().unwrap();
+        let circuit = circuits.entry(report.venue.clone()).or_default();
+        circuit.probe_started = None;
+        let failed = match report.status {
+            VenueStatus::Success | VenueStatus::Stale => false,
+            _ if is_venue_failure(report) => true,
+            _ => return,
+        };
+        if !failed {
+            if circuit.state != CircuitState::Closed {
+                info!(venue = %report.venue, "circuit closed");
+            }
+            *circuit = Circuit::default();
+            return;
+        }
+        circuit.consecutive_failures += 1;
+        circuit.last_error = report.error;
+        let reopen = circuit.state == CircuitState::HalfOpen;
+        if reopen || circuit.consecutive_failures >= self.config.failure_threshold {
+            if circuit.state != CircuitState::Open {
+                warn!(
+                    venue = %report.venue,
+                    consecutive_failures = circuit.consecutive_failures,
+                    open_ms = self.config.open_duration.as_millis() as u64,
+                    "circuit opened"
+                );
+            }
+            circuit.state = CircuitState::Open;
+            circuit.opened_at = Some(Instant::now());
+        }
+    }
+
+    // Health of every venue recorded so far, sorted by venue
+    pub fn health(&self) -> Vec<VenueCircuit> {
+        let now = unix_time_ms();
+        let circuits = self.circuits.lock().unwrap();
+        let mut health: Vec<VenueCircuit> = circuits
+            .iter()
+            .map(|(venue, circuit)| VenueCircuit {
+                venue: venue.clone(),
+                state: circuit.state,
+                consecutive_failures: circuit.consecutive_failures,
+                last_error: circuit.last_error,
+                retry_at_ms: match (circuit.state, circuit.opened_at) {
+                    (CircuitState::Open, Some(opened_at)) => {
+                        let remaining = (opened_at + self.config.open_duration)
+                            .saturating_duration_since(Instant::now());
+                        Some(now + remaining.as_millis() as u64)
+                    }
+                    _ => None,
+                },
+            })
+            .collect();
+        health.sort_by(|a, b| a.venue.cmp(&b.venue));
+        health
+    }
+}
+
+// Timeouts, network errors, 5xx answers and the venue's own rate limiting. Rejections by the
+// client side RateLimiter, 4xx answers and unparseable payloads do not say the venue is down.
+fn is_venue_failure(report: &VenueReport) -> bool {
+    match report.status {
+        VenueStatus::TimedOut => true,
+        VenueStatus::Failed => match report.error {
+            Some(
+                ErrorKind::Timeout
+                | ErrorKind::Network
+                | ErrorKind::Unavailable
+                | ErrorKind::ServerError,
+            ) => true,
+            Some(ErrorKind::RateLimited) => report.venue_error.is_some(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::{VenueError, VenueErrorKind},
+        types::Exchange,
+    };
+
+    fn report(venue: &str, status: VenueStatus) -> VenueReport {
+        VenueReport {
+            venue: venue.to_string(),
+            status,
+            latency: Duration::ZERO,
+            bid_levels: 0,
+            ask_levels: 0,
+            error: (status != VenueStatus::Success).then_some(ErrorKind::Network),
+            error_message: None,
+            venue_error: None,
+            retryable: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_circuit_opens_and_recovers() {
+        let breaker = CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 2,
+            open_duration: Duration::from_millis(50),
+        });
+        assert!(breaker.allow("Gemini"));
+        breaker.record(&report("Gemini", VenueStatus::Failed));
+        assert!(breaker.allow("Gemini"));
+        breaker.record(&report("Gemini", VenueStatus::TimedOut));
+        assert!(!breaker.allow("Gemini"));
+        let health = breaker.health();
+        assert_eq!(health[0].state, CircuitState::Open);
+        assert_eq!(health[0].consecutive_failures, 2);
+        assert!(health[0].retry_at_ms.is_some());
+
+        // The probe after the open duration fails and reopens the circuit at once
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(breaker.allow("Gemini"));
+        assert_eq!(breaker.health()[0].state, CircuitState::HalfOpen);
+        // Only one probe at a time
+        assert!(!breaker.allow("Gemini"));
+        breaker.record(&report("Gemini", VenueStatus::Failed));
+        assert!(!breaker.allow("Gemini"));
+
+        // A successful probe closes it
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(breaker.allow("Gemini"));
+        breaker.record(&report("Gemini", VenueStatus::Success));
+        let health = breaker.health();
+        assert_eq!(health[0].state, CircuitState::Closed);
+        assert_eq!(health[0].consecutive_failures, 0);
+        assert_eq!(health[0].last_error, None);
+    }
+
+    #[test]
+    fn test_client_side_failures_not_counted() {
+        let breaker = CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 1,
+            open_duration: Duration::from_secs(30),
+        });
+        // Rejected by our own RateLimiter, and a 400 from the venue
+        let throttled = VenueReport {
+            error: Some(ErrorKind::RateLimited),
+            ..report("Gemini", VenueStatus::Failed)
+        };
+        let rejected = VenueReport {
+            error: Some(ErrorKind::BadRequest),
+            ..report("Gemini", VenueStatus::Failed)
+        };
+        breaker.record(&throttled);
+        breaker.record(&rejected);
+        assert!(breaker.allow("Gemini"));
+        assert_eq!(breaker.health()[0].consecutive_failures, 0);
+
+        // A 429 from the venue itself does count
+        let venue_throttled = VenueReport {
+            venue_error: Some(VenueError {
+                venue: Exchange::Gemini,
+                kind: VenueErrorKind::RateLimited,
+                status: 429,
+                code: None,
+                message: "Too many requests".to_string(),
+                retry_after: None,
+            }),
+            ..throttled
+        };
+        breaker.record(&venue_throttled);
+        assert!(!breaker.allow("Gemini"));
+    }
+}
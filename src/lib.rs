@@ -0,0 +1,18 @@
+pub mod aggregator;
+pub mod analytics;
+pub mod arbitrage;
+pub mod backtest;
+pub mod data_providers;
+pub mod decimal_string;
+pub mod error;
+pub mod feed;
+pub mod health;
+pub mod metrics;
+pub mod order_book;
+pub mod output;
+pub mod planner;
+pub mod rate_limiter;
+pub mod recorder;
+pub mod server;
+pub mod tui;
+pub mod types;
@@ -1,49 +1,728 @@
-mod aggregator;
-mod data_providers;
-mod error;
-mod order_book;
-mod rate_limiter;
-mod types;
-use std::sync::Arc;
-
-use crate::data_providers::DataProvider;
-use crate::data_providers::gemini::GeminiExchange;
-use crate::types::Product;
-use crate::{
+use std::{
+    io::{self, Write},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use dotenvy::dotenv;
+use order_book_aggregator::aggregator::AggregationReport;
+use order_book_aggregator::analytics::{AnalyticsConfig, DEFAULT_IMBALANCE_LEVELS, MarketStats};
+use order_book_aggregator::arbitrage::ArbitrageScanner;
+use order_book_aggregator::backtest::{Execution, Strategy, Timeline};
+use order_book_aggregator::data_providers::DataProvider;
+use order_book_aggregator::data_providers::gemini::GeminiExchange;
+use order_book_aggregator::feed::{BookFeed, DEFAULT_REFRESH_INTERVAL};
+use order_book_aggregator::health::{
+    CircuitBreakerConfig, DEFAULT_FAILURE_THRESHOLD, DEFAULT_OPEN_DURATION,
+};
+use order_book_aggregator::metrics::Metrics;
+use order_book_aggregator::order_book::{BucketWidth, DEFAULT_IMPACT_SIZES};
+use order_book_aggregator::output::{self, OutputFormat, Quote};
+use order_book_aggregator::planner::{SchedulePlanner, SliceProfile};
+use order_book_aggregator::recorder::{RecordReader, Recorder, RecordingProvider};
+use order_book_aggregator::server::{self, AppState, DEFAULT_MAX_FEED_AGE};
+use order_book_aggregator::tui;
+use order_book_aggregator::types::{Exchange, Product, Side};
+use order_book_aggregator::{
     aggregator::OrderBookAggregator, data_providers::coinbase::CoinbaseExchange,
     error::AggregatorError,
 };
-use clap::Parser;
-use dotenvy::dotenv;
+use tokio::{net::TcpListener, sync::mpsc, task::JoinSet};
+use tracing_subscriber::EnvFilter;
 
 #[derive(Parser, Debug)]
 #[command(name = "order-book-aggregator")]
 #[command(about = "Order Book Aggregator", long_about = None)]
 struct Args {
-    #[arg(long, default_value = "10.0")]
-    qty: f64,
+    #[command(subcommand)]
+    command: Command,
+    /// Log filter such as `info` or `order_book_aggregator=debug`, RUST_LOG when not given
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+    /// Format of the logs written to stderr
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+// Product and venues a command works on
+#[derive(clap::Args, Debug)]
+struct MarketArgs {
+    /// Product to aggregate, e.g. BTC-USD or BTCUSD
+    #[arg(long, default_value = "BTC-USD")]
+    product: Product,
+    /// Comma separated venues to include, all venues when empty
+    #[arg(long, value_delimiter = ',')]
+    exchanges: Vec<Exchange>,
+    /// Append every venue fetch to this compressed recording
+    #[arg(long)]
+    record: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Price a market order against the consolidated book
+    Quote {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Side to price, or both
+        #[arg(long, value_enum, default_value_t = QuoteSide::Both)]
+        side: QuoteSide,
+        /// Base quantity to buy or sell
+        #[arg(long, default_value = "10.0", conflicts_with = "notional", value_parser = positive)]
+        qty: f64,
+        /// Quote currency amount to spend or receive instead of a quantity
+        #[arg(long, value_parser = positive)]
+        notional: Option<f64>,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Average price and slippage against mid for a range of sizes, both sides
+    Impact {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Comma separated base quantities, 1,5,10,50 when empty
+        #[arg(long, value_delimiter = ',')]
+        sizes: Vec<f64>,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Spread, mid, microprice, depth and order flow imbalance and depth near mid, consolidated
+    /// and per venue
+    Stats {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Levels per side looked at by the depth and order flow imbalances
+        #[arg(long, default_value_t = DEFAULT_IMBALANCE_LEVELS)]
+        levels: usize,
+        /// Aggregate a second round this many milliseconds after the first and report the order
+        /// flow imbalance between the two
+        #[arg(long)]
+        flow_ms: Option<u64>,
+        /// Comma separated distances from mid in percent, 0.1,0.5,1 when empty
+        #[arg(long, value_delimiter = ',')]
+        bands: Vec<f64>,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Print the consolidated book
+    Book {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Levels per side, or buckets per side with --bucket
+        #[arg(long, default_value = "10")]
+        depth: usize,
+        /// Group levels into price buckets, e.g. 10 for $10 or 1bp for 1 basis point of mid
+        #[arg(long)]
+        bucket: Option<BucketWidth>,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Keep the consolidated book refreshing in the terminal until interrupted
+    Watch {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Levels per side
+        #[arg(long, default_value = "10")]
+        depth: usize,
+        /// Milliseconds between two aggregation rounds
+        #[arg(long, default_value_t = DEFAULT_REFRESH_INTERVAL.as_millis() as u64)]
+        refresh_ms: u64,
+    },
+    /// Interactive ladder, venue health and quote panel for desk use
+    Tui {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Levels per side
+        #[arg(long, default_value = "15")]
+        depth: usize,
+        /// Milliseconds between two aggregation rounds
+        #[arg(long, default_value_t = DEFAULT_REFRESH_INTERVAL.as_millis() as u64)]
+        refresh_ms: u64,
+    },
+    /// Fetch every venue once and report its status and latency
+    Venues {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Scan the venue books for cross venue arbitrage
+    Arb {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Taker fee in basis points applied to every venue
+        #[arg(long, default_value = "0.0")]
+        fee_bps: f64,
+        /// Ignore opportunities with a smaller expected profit
+        #[arg(long, default_value = "0.0")]
+        min_profit: f64,
+        /// Keep refreshing the venue books and print opportunities as they appear until
+        /// interrupted
+        #[arg(long)]
+        watch: bool,
+        /// Milliseconds between two aggregation rounds with --watch
+        #[arg(long, default_value_t = DEFAULT_REFRESH_INTERVAL.as_millis() as u64)]
+        refresh_ms: u64,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Split a parent order into child slices and re-plan them from the live book every interval
+    Plan {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Side of the parent order
+        #[arg(long, value_enum, default_value_t = Side::Buy)]
+        side: Side,
+        /// Base quantity of the parent order
+        #[arg(long, default_value = "10.0")]
+        qty: f64,
+        /// Seconds over which the order is worked
+        #[arg(long, default_value = "600")]
+        horizon_secs: u64,
+        /// Child orders, one per interval, at most 10000
+        #[arg(long, default_value = "10")]
+        slices: usize,
+        /// Comma separated relative volume per interval for a VWAP schedule, TWAP when empty
+        #[arg(long, value_delimiter = ',')]
+        volume_profile: Vec<f64>,
+        /// Print the initial plan and exit instead of re-planning every interval
+        #[arg(long)]
+        once: bool,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Replay a recording and evaluate an execution strategy against it
+    Backtest {
+        /// Recording written with --record
+        #[arg(long)]
+        recording: PathBuf,
+        /// Recorded product to replay, e.g. BTC-USD or BTCUSD
+        #[arg(long, default_value = "BTC-USD")]
+        product: Product,
+        /// Comma separated venues to trade on, all recorded venues when empty
+        #[arg(long, value_delimiter = ',')]
+        exchanges: Vec<Exchange>,
+        /// Side of the parent order
+        #[arg(long, value_enum, default_value_t = QuoteSide::Buy)]
+        side: QuoteSide,
+        /// Base quantity of the parent order
+        #[arg(long, default_value = "10.0")]
+        qty: f64,
+        /// Execution strategy to evaluate
+        #[arg(long, value_enum, default_value_t = BacktestStrategy::Immediate)]
+        strategy: BacktestStrategy,
+        /// Child orders of a TWAP
+        #[arg(long, default_value = "10")]
+        slices: usize,
+        /// Milliseconds between two TWAP child orders
+        #[arg(long, default_value = "60000")]
+        interval_ms: u64,
+        /// Milliseconds between two replayed aggregation rounds
+        #[arg(long, default_value = "1000")]
+        step_ms: u64,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+    },
+    /// Serve the HTTP and/or gRPC APIs from a live feed
+    Serve {
+        #[command(flatten)]
+        market: MarketArgs,
+        /// Address of the HTTP API
+        #[arg(long, required_unless_present = "grpc")]
+        http: Option<SocketAddr>,
+        /// Address of the gRPC API
+        #[arg(long)]
+        grpc: Option<SocketAddr>,
+        /// Milliseconds between two aggregation rounds
+        #[arg(long, default_value_t = DEFAULT_REFRESH_INTERVAL.as_millis() as u64)]
+        refresh_ms: u64,
+        /// Failed rounds in a row after which a venue is skipped, 0 disables the circuit breaker
+        #[arg(long, default_value_t = DEFAULT_FAILURE_THRESHOLD)]
+        breaker_failures: u32,
+        /// Seconds a failing venue is skipped before it is probed again
+        #[arg(long, default_value_t = DEFAULT_OPEN_DURATION.as_secs())]
+        breaker_open_secs: u64,
+        /// Seconds without a successful round after which /venues/health answers 503
+        #[arg(long, default_value_t = DEFAULT_MAX_FEED_AGE.as_secs())]
+        max_feed_age_secs: u64,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum LogFormat {
+    Text,
+    /// One JSON object per event, carrying the fields of its spans
+    Json,
+}
+
+// Filter used when neither --log-level nor RUST_LOG is set. Warnings only, but the
+// aggregation and venue_fetch spans are info so they must stay enabled to show on them.
+const DEFAULT_LOG_FILTER: &str = "warn,order_book_aggregator=info";
+
+// Opportunities buffered between the arbitrage scanner and stdout with arb --watch
+const ARBITRAGE_BUFFER: usize = 16;
+
+// Send tracing events to stderr so they stay out of the command output
+fn init_logging(level: Option<&str>, format: LogFormat) -> Result<(), AggregatorError> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level),
+        None => {
+            EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(DEFAULT_LOG_FILTER))
+        }
+    }
+    .map_err(|error| AggregatorError::InvalidLogFilter(error.to_string()))?;
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().with_current_span(false).init(),
+    }
+    Ok(())
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum QuoteSide {
+    Buy,
+    Sell,
+    Both,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum BacktestStrategy {
+    /// Sweep the whole order at once
+    Immediate,
+    /// Equal child orders every --interval-ms
+    Twap,
+}
+
+impl QuoteSide {
+    fn sides(self) -> &'static [Side] {
+        match self {
+            QuoteSide::Buy => &[Side::Buy],
+            QuoteSide::Sell => &[Side::Sell],
+            QuoteSide::Both => &[Side::Buy, Side::Sell],
+        }
+    }
+}
+
+impl MarketArgs {
+    // Providers of the selected venues, every venue when none was selected
+    fn providers(&self) -> Result<Vec<Arc<dyn DataProvider>>, AggregatorError> {
+        let recorder = match &self.record {
+            Some(path) => Some(Arc::new(Recorder::open(path)?)),
+            None => None,
+        };
+        Ok(Exchange::VENUES
+            .into_iter()
+            .filter(|exchange| self.exchanges.is_empty() || self.exchanges.contains(exchange))
+            .map(|exchange| -> Arc<dyn DataProvider> {
+                let provider: Arc<dyn DataProvider> = match exchange {
+                    Exchange::Coinbase => Arc::new(CoinbaseExchange::new()),
+                    Exchange::Gemini => Arc::new(GeminiExchange::new()),
+                    Exchange::AggregatedExchange => unreachable!("not a venue"),
+                };
+                match &recorder {
+                    Some(recorder) => {
+                        Arc::new(RecordingProvider::new(provider, Arc::clone(recorder)))
+                    }
+                    None => provider,
+                }
+            })
+            .collect())
+    }
+
+    fn aggregator(&self) -> Result<OrderBookAggregator, AggregatorError> {
+        Ok(OrderBookAggregator::new(
+            self.providers()?,
+            self.product.clone(),
+        ))
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), AggregatorError> {
     let args = Args::parse();
-    let quantity = args.qty;
     // Load environment variables from .env file
     dotenv()?;
+    // Logs would draw over the terminal UI
+    if !matches!(args.command, Command::Tui { .. }) {
+        init_logging(args.log_level.as_deref(), args.log_format)?;
+    }
 
-    let data_providers = vec![
-        Arc::new(CoinbaseExchange::new()) as Arc<dyn DataProvider>,
-        Arc::new(GeminiExchange::new()) as Arc<dyn DataProvider>,
-    ];
+    match args.command {
+        Command::Quote {
+            market,
+            side,
+            qty,
+            notional,
+            format,
+        } => {
+            let report = aggregate(&market).await?;
+            let quotes = side
+                .sides()
+                .iter()
+                .map(|&side| {
+                    let fills = match notional {
+                        Some(notional) => report
+                            .book
+                            .calculate_best_offer_for_notional(side, notional)?,
+                        None => report.book.calculate_best_offer(side, qty)?,
+                    };
+                    Ok(Quote::new(side, fills))
+                })
+                .collect::<Result<Vec<_>, AggregatorError>>()?;
+            output::write_quotes(&mut io::stdout().lock(), format, &market.product, &quotes)
+        }
+        Command::Impact {
+            market,
+            sizes,
+            format,
+        } => {
+            let sizes = if sizes.is_empty() {
+                DEFAULT_IMPACT_SIZES.to_vec()
+            } else {
+                sizes
+            };
+            let report = aggregate(&market).await?;
+            let curve = report.book.impact_curve(&sizes)?;
+            output::write_impact(&mut io::stdout().lock(), format, &market.product, &curve)
+        }
+        Command::Stats {
+            market,
+            levels,
+            flow_ms,
+            bands,
+            format,
+        } => {
+            let mut config = AnalyticsConfig {
+                imbalance_levels: levels,
+                ..AnalyticsConfig::default()
+            };
+            if !bands.is_empty() {
+                config.depth_bands_pct = bands;
+            }
+            let aggregator = market.aggregator()?;
+            let mut report = aggregator.fetch_and_aggregate_data().await?;
+            let mut previous = None;
+            if let Some(flow_ms) = flow_ms {
+                tokio::time::sleep(Duration::from_millis(flow_ms)).await;
+                let next = aggregator.fetch_and_aggregate_data().await?;
+                previous = Some(std::mem::replace(&mut report, next));
+            }
+            let stats = MarketStats::new(&report, previous.as_ref(), &config);
+            output::write_stats(&mut io::stdout().lock(), format, &market.product, &stats)
+        }
+        Command::Book {
+            market,
+            depth,
+            bucket,
+            format,
+        } => {
+            let report = aggregate(&market).await?;
+            let mut stdout = io::stdout().lock();
+            match bucket {
+                Some(width) => {
+                    let book = report.book.bucketed(width, depth);
+                    output::write_buckets(&mut stdout, format, &market.product, &book)
+                }
+                None => {
+                    let book = report.book.top_n(depth);
+                    output::write_book(&mut stdout, format, &market.product, &book)
+                }
+            }
+        }
+        Command::Watch {
+            market,
+            depth,
+            refresh_ms,
+        } => watch(&market, depth, Duration::from_millis(refresh_ms)).await,
+        Command::Tui {
+            market,
+            depth,
+            refresh_ms,
+        } => {
+            // The feed keeps refreshing until it is dropped at the end of this block
+            let feed = BookFeed::spawn(market.aggregator()?, Duration::from_millis(refresh_ms));
+            let app = tui::App::new(market.product.clone(), depth, feed.subscribe());
+            tokio::task::spawn_blocking(move || {
+                let mut terminal = ratatui::init();
+                let result = app.run(&mut terminal);
+                ratatui::restore();
+                result
+            })
+            .await
+            .map_err(std::io::Error::other)?
+        }
+        Command::Venues { market, format } => venues(&market, format).await,
+        Command::Arb {
+            market,
+            fee_bps,
+            min_profit,
+            watch,
+            refresh_ms,
+            format,
+        } => {
+            let scanner = ArbitrageScanner::new()
+                .with_default_fee_bps(fee_bps)
+                .with_min_profit(min_profit);
+            if watch {
+                let refresh_interval = Duration::from_millis(refresh_ms);
+                return watch_arbitrage(&market, scanner, refresh_interval, format).await;
+            }
+            let report = aggregate(&market).await?;
+            let books: Vec<_> = report
+                .venue_books
+                .iter()
+                .map(|(_, book)| book.as_ref())
+                .collect();
+            let opportunities = scanner.scan(&books);
+            output::write_arbitrage(
+                &mut io::stdout().lock(),
+                format,
+                &market.product,
+                &opportunities,
+            )
+        }
+        Command::Plan {
+            market,
+            side,
+            qty,
+            horizon_secs,
+            slices,
+            volume_profile,
+            once,
+            format,
+        } => {
+            let mut planner =
+                SchedulePlanner::new(side, qty, Duration::from_secs(horizon_secs), slices)?;
+            if !volume_profile.is_empty() {
+                planner = planner.with_profile(SliceProfile::Volume(volume_profile));
+            }
+            plan(&market, planner, once, format).await
+        }
+        Command::Backtest {
+            recording,
+            product,
+            exchanges,
+            side,
+            qty,
+            strategy,
+            slices,
+            interval_ms,
+            step_ms,
+            format,
+        } => {
+            let records = RecordReader::open(&recording)?.collect::<Result<Vec<_>, _>>()?;
+            let timeline =
+                Timeline::replay(records, product.clone(), Duration::from_millis(step_ms)).await?;
+            let strategy = match strategy {
+                BacktestStrategy::Immediate => Strategy::Immediate,
+                BacktestStrategy::Twap => Strategy::Twap {
+                    slices,
+                    interval: Duration::from_millis(interval_ms),
+                },
+            };
+            let venues: Vec<String> = exchanges.iter().map(ToString::to_string).collect();
+            let results = side
+                .sides()
+                .iter()
+                .map(|&side| {
+                    timeline.run(&Execution {
+                        side,
+                        quantity: qty,
+                        strategy,
+                        venues: venues.clone(),
+                    })
+                })
+                .collect::<Result<Vec<_>, AggregatorError>>()?;
+            output::write_backtests(&mut io::stdout().lock(), format, &product, &results)
+        }
+        Command::Serve {
+            market,
+            http,
+            grpc,
+            refresh_ms,
+            breaker_failures,
+            breaker_open_secs,
+            max_feed_age_secs,
+        } => {
+            let metrics = Arc::new(Metrics::new());
+            let mut aggregator = market.aggregator()?.with_metrics(Arc::clone(&metrics));
+            if breaker_failures > 0 {
+                aggregator = aggregator.with_circuit_breaker(CircuitBreakerConfig {
+                    failure_threshold: breaker_failures,
+                    open_duration: Duration::from_secs(breaker_open_secs),
+                });
+            }
+            let feed = BookFeed::spawn(aggregator, Duration::from_millis(refresh_ms));
+            let state = AppState::new(vec![feed])
+                .with_metrics(metrics)
+                .with_max_feed_age(Duration::from_secs(max_feed_age_secs));
+            run_servers(http, grpc, Arc::new(state)).await
+        }
+    }
+}
 
-    let aggregator = OrderBookAggregator::new(data_providers, Product::BTCUSD);
-    let aggregated_book = aggregator.fetch_and_aggregate_data().await?;
+// Parse a strictly positive, finite amount such as --qty
+fn positive(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(amount) if amount.is_finite() && amount > 0.0 => Ok(amount),
+        Ok(_) => Err("must be a positive number".to_string()),
+        Err(error) => Err(error.to_string()),
+    }
+}
 
-    let best_buy_price = aggregated_book.calculate_best_buy_offer(quantity)?;
-    println!("To buy  {} BTC : ${:?}", quantity, best_buy_price);
+// Run one aggregation round, venues left out of it are logged as warnings
+async fn aggregate(market: &MarketArgs) -> Result<AggregationReport, AggregatorError> {
+    market.aggregator()?.fetch_and_aggregate_data().await
+}
+
+// Scan the venue books of every aggregation round for arbitrage and print what is found until
+// Ctrl-C
+async fn watch_arbitrage(
+    market: &MarketArgs,
+    scanner: ArbitrageScanner,
+    refresh_interval: Duration,
+    format: OutputFormat,
+) -> Result<(), AggregatorError> {
+    let feed = BookFeed::spawn(market.aggregator()?, refresh_interval);
+    let (sender, mut opportunities) = mpsc::channel(ARBITRAGE_BUFFER);
+    tokio::spawn(scanner.run(feed.venue_books(), sender));
+    let mut first = true;
+    loop {
+        let opportunity = tokio::select! {
+            opportunity = opportunities.recv() => match opportunity {
+                Some(opportunity) => opportunity,
+                None => return Ok(()),
+            },
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        };
+        let mut stdout = io::stdout().lock();
+        output::write_arbitrage_update(&mut stdout, format, &market.product, &opportunity, first)?;
+        stdout.flush()?;
+        first = false;
+    }
+}
 
-    let best_sell_price = aggregated_book.calculate_best_sell_offer(quantity)?;
-    println!("To sell {} BTC : ${:?}", quantity, best_sell_price);
+// Redraw the book after every aggregation round until Ctrl-C
+async fn watch(
+    market: &MarketArgs,
+    depth: usize,
+    refresh_interval: Duration,
+) -> Result<(), AggregatorError> {
+    let feed = BookFeed::spawn(market.aggregator()?, refresh_interval);
+    let mut updates = feed.subscribe();
+    loop {
+        tokio::select! {
+            changed = updates.changed() => {
+                if changed.is_err() {
+                    return Ok(());
+                }
+            }
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+        let state = updates.borrow_and_update().clone();
+        let mut stdout = io::stdout().lock();
+        // Clear the screen and move the cursor home
+        write!(stdout, "\x1b[2J\x1b[H")?;
+        writeln!(
+            stdout,
+            "{} updated at {}",
+            market.product, state.updated_at_ms
+        )?;
+        if let Some(error) = &state.last_error {
+            writeln!(stdout, "Last round failed: {}", error)?;
+        }
+        for venue in &state.venues {
+            writeln!(
+                stdout,
+                "{:<10} {:<10} {:>6} ms",
+                venue.venue,
+                venue.status.to_string(),
+                venue.latency.as_millis()
+            )?;
+        }
+        if let Some(report) = &state.report {
+            writeln!(stdout)?;
+            let book = report.book.top_n(depth);
+            output::write_book(&mut stdout, OutputFormat::Table, &market.product, &book)?;
+        }
+        stdout.flush()?;
+    }
+}
 
+// Re-plan from a fresh consolidated book at the start of every interval until the schedule
+// is done or Ctrl-C. Nothing is sent to the venues, every slice is assumed to fill as planned.
+async fn plan(
+    market: &MarketArgs,
+    mut planner: SchedulePlanner,
+    once: bool,
+    format: OutputFormat,
+) -> Result<(), AggregatorError> {
+    let aggregator = market.aggregator()?;
+    let mut ticker = tokio::time::interval(planner.interval());
+    while !planner.is_done() {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+        let plan = planner.replan(&aggregator).await?;
+        output::write_plan(&mut io::stdout().lock(), format, &market.product, &plan)?;
+        if once {
+            break;
+        }
+        planner.record_fill(plan.slices.first().map_or(0.0, |slice| slice.quantity));
+    }
     Ok(())
 }
+
+// Run one aggregation round and print the outcome of every venue, failed rounds included
+async fn venues(market: &MarketArgs, format: OutputFormat) -> Result<(), AggregatorError> {
+    let venues = match aggregate(market).await {
+        Ok(report) => report.venues,
+        Err(error) => match error.venue_reports() {
+            Some(venues) => venues.to_vec(),
+            None => return Err(error),
+        },
+    };
+    output::write_venues(&mut io::stdout().lock(), format, &market.product, &venues)
+}
+
+// Serve the HTTP and/or gRPC APIs from the same live feeds
+async fn run_servers(
+    http: Option<SocketAddr>,
+    grpc: Option<SocketAddr>,
+    state: Arc<AppState>,
+) -> Result<(), AggregatorError> {
+    let mut servers = JoinSet::new();
+    if let Some(address) = http {
+        let listener = TcpListener::bind(address).await?;
+        let address = listener.local_addr()?;
+        tracing::info!(%address, "serving order book HTTP API");
+        servers.spawn(server::serve(listener, Arc::clone(&state)));
+    }
+    if let Some(address) = grpc {
+        let listener = TcpListener::bind(address).await?;
+        let address = listener.local_addr()?;
+        tracing::info!(%address, "serving order book gRPC API");
+        servers.spawn(server::grpc::serve(listener, Arc::clone(&state)));
+    }
+    // Stop as soon as either server exits
+    match servers.join_next().await {
+        Some(Ok(result)) => result,
+        Some(Err(join_error)) => Err(std::io::Error::other(join_error).into()),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,340 @@
+use crate::{
+    aggregator::{AggregationReport, VenueReport, VenueStatus},
+    error::{AggregatorError, ErrorKind},
+    order_book::OrderBook,
+    types::Product,
+};
+use prometheus_client::{
+    encoding::{EncodeLabelSet, text::encode},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        gauge::Gauge,
+        histogram::{Histogram, exponential_buckets},
+    },
+    registry::{Registry, Unit},
+};
+use std::{sync::atomic::AtomicU64, time::Duration};
+
+// Content type of the text exposition served on /metrics
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// Label of the consolidated book in per venue metrics
+const CONSOLIDATED: &str = "agg";
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct VenueLabels {
+    product: String,
+    venue: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ErrorLabels {
+    product: String,
+    venue: String,
+    kind: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct SideLabels {
+    product: String,
+    venue: String,
+    side: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RoundLabels {
+    product: String,
+    result: String,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ProductLabels {
+    product: String,
+}
+
+type HistogramFamily<L> = Family<L, Histogram, fn() -> Histogram>;
+
+// 5 ms up to about 10 s
+fn latency_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.005, 2.0, 12))
+}
+
+// Prometheus metrics of the aggregators sharing this registry, updated after every round.
+// Venue errors are counted by ErrorKind. Rate limit rejections, by the venue RateLimiters or
+// by the venue itself, surface there as `rate_limited` and are also counted on their own.
+pub struct Metrics {
+    registry: Registry,
+    fetch_duration: HistogramFamily<VenueLabels>,
+    fetch_errors: Family<ErrorLabels, Counter>,
+    rate_limited: Family<VenueLabels, Counter>,
+    book_levels: Family<SideLabels, Gauge>,
+    spread_bps: Family<VenueLabels, Gauge<f64, AtomicU64>>,
+    rounds: Family<RoundLabels, Counter>,
+    round_duration: HistogramFamily<ProductLabels>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let mut metrics = Metrics {
+            registry: Registry::with_prefix("orderbook"),
+            fetch_duration: Family::new_with_constructor(latency_histogram),
+            fetch_errors: Family::default(),
+            rate_limited: Family::default(),
+            book_levels: Family::default(),
+            spread_bps: Family::default(),
+            rounds: Family::default(),
+            round_duration: Family::new_with_constructor(latency_histogram),
+        };
+        let registry = &mut metrics.registry;
+        registry.register_with_unit(
+            "venue_fetch_duration",
+            "Time until a venue answered a book request, or until the deadline passed",
+            Unit::Seconds,
+            metrics.fetch_duration.clone(),
+        );
+        registry.register(
+            "venue_fetch_errors",
+            "Venue fetches left out of a round, by error kind",
+            metrics.fetch_errors.clone(),
+        );
+        registry.register(
+            "venue_rate_limited",
+            "Venue fetches rejected by the client side rate limiter or by the venue",
+            metrics.rate_limited.clone(),
+        );
+        registry.register(
+            "book_levels",
+            "Price levels per side of the latest venue and consolidated (agg) books",
+            metrics.book_levels.clone(),
+        );
+        registry.register(
+            "spread_bps",
+            "Spread of the latest venue and consolidated (agg) books in basis points of mid",
+            metrics.spread_bps.clone(),
+        );
+        registry.register(
+            "aggregation_rounds",
+            "Aggregation rounds by result",
+            metrics.rounds.clone(),
+        );
+        registry.register_with_unit(
+            "aggregation_duration",
+            "Duration of a whole aggregation round",
+            Unit::Seconds,
+            metrics.round_duration.clone(),
+        );
+        metrics
+    }
+
+    // Record one aggregation round of `product`
+    pub fn record_round(
+        &self,
+        product: &Product,
+        result: &Result<AggregationReport, AggregatorError>,
+        elapsed: Duration,
+    ) {
+        let product = product.to_string();
+        let outcome = match result {
+            Ok(_) => "success".to_string(),
+            Err(error) => error.kind().to_string(),
+        };
+        self.rounds
+            .get_or_create(&RoundLabels {
+                product: product.clone(),
+                result: outcome,
+            })
+            .inc();
+        self.round_duration
+            .get_or_create(&ProductLabels {
+                product: product.clone(),
+            })
+            .observe(elapsed.as_secs_f64());
+        // Book gauges follow the venue reports of the round, so a venue that failed, went
+        // stale or was skipped stops exporting the depth and spread of its last good book
+        let (book, venues, venue_books) = match result {
+            Ok(report) => (
+                Some(&report.book),
+                report.venues.as_slice(),
+                report.venue_books.as_slice(),
+            ),
+            Err(error) => (None, error.venue_reports().unwrap_or_default(), &[][..]),
+        };
+        match book {
+            Some(book) => self.record_book(&product, CONSOLIDATED, book),
+            None => self.clear_book(&product, CONSOLIDATED),
+        }
+        for venue in venues {
+            match venue_books.iter().find(|(name, _)| *name == venue.venue) {
+                Some((_, book)) => self.record_book(&product, &venue.venue, book),
+                None => self.clear_book(&product, &venue.venue),
+            }
+        }
+    }
+
+    // Record the fetch of one venue. Called for every venue, including the ones whose round
+    // failed as a whole.
+    pub fn record_venue(&self, product: &Product, venue: &VenueReport) {
+        let labels = VenueLabels {
+            product: product.to_string(),
+            venue: venue.venue.clone(),
+        };
+        // Skipped venues were not fetched, only their error is counted
+        if venue.status != VenueStatus::Skipped {
+            self.fetch_duration
+                .get_or_create(&labels)
+                .observe(venue.latency.as_secs_f64());
+        }
+        let Some(kind) = venue.error else {
+            return;
+        };
+        self.fetch_errors
+            .get_or_create(&ErrorLabels {
+                product: labels.product.clone(),
+                venue: labels.venue.clone(),
+                kind: kind.to_string(),
+            })
+            .inc();
+        if kind == ErrorKind::RateLimited {
+            self.rate_limited.get_or_create(&labels).inc();
+        }
+    }
+
+    fn record_book(&self, product: &str, venue: &str, book: &OrderBook) {
+        for (side, levels) in [("bid", book.bids.len()), ("ask", book.asks.len())] {
+            self.book_levels
+                .get_or_create(&SideLabels {
+                    product: product.to_string(),
+                    venue: venue.to_string(),
+                    side: side.to_string(),
+                })
+                .set(levels as i64);
+        }
+        let labels = VenueLabels {
+            product: product.to_string(),
+            venue: venue.to_string(),
+        };
+        let spread_bps = match (book.best_bid(), book.best_ask(), book.mid_price()) {
+            (Some(bid), Some(ask), Some(mid)) => Some((ask.price.0 - bid.price.0) / mid * 10_000.0),
+            _ => None,
+        };
+        match spread_bps {
+            Some(spread_bps) => {
+                self.spread_bps.get_or_create(&labels).set(spread_bps);
+            }
+            // A one sided book has no spread, drop the stale value
+            None => {
+                self.spread_bps.remove(&labels);
+            }
+        }
+    }
+
+    // Drop the book gauges of a venue without a book this round
+    fn clear_book(&self, product: &str, venue: &str) {
+        for side in ["bid", "ask"] {
+            self.book_levels.remove(&SideLabels {
+                product: product.to_string(),
+                venue: venue.to_string(),
+                side: side.to_string(),
+            });
+        }
+        self.spread_bps.remove(&VenueLabels {
+            product: product.to_string(),
+            venue: venue.to_string(),
+        });
+    }
+
+    // Every metric in the OpenMetrics text exposition format
+    pub fn encode(&self) -> String {
+        let mut text = String::new();
+        encode(&mut text, &self.registry).expect("writing to a String does not fail");
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggregator::OrderBookAggregator, data_providers::mock::MockProvider, types::Exchange,
+    };
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_round_metrics() {
+        let mut book = OrderBook::new(Exchange::Coinbase);
+        book.add_bid(99.0, 1.0);
+        book.add_ask(101.0, 1.0);
+        let coinbase = Arc::new(MockProvider::new("Coinbase", book));
+        let gemini = Arc::new(
+            MockProvider::new("Gemini", OrderBook::new(Exchange::Gemini)).with_error(
+                AggregatorError::RateLimitExceeded("Rate limit exceeded".to_string()),
+            ),
+        );
+        let metrics = Arc::new(Metrics::new());
+        let aggregator = OrderBookAggregator::new(vec![coinbase, gemini], Product::BTCUSD)
+            .with_metrics(Arc::clone(&metrics));
+        aggregator.fetch_and_aggregate_data().await.unwrap();
+
+        let text = metrics.encode();
+        let has = |line: &str| text.lines().any(|l| l == line);
+        assert!(has(
+            r#"orderbook_aggregation_rounds_total{product="BTC-USD",result="success"} 1"#
+        ));
+        assert!(has(
+            r#"orderbook_venue_fetch_errors_total{product="BTC-USD",venue="Gemini",kind="rate_limited"} 1"#
+        ));
+        assert!(has(
+            r#"orderbook_venue_rate_limited_total{product="BTC-USD",venue="Gemini"} 1"#
+        ));
+        assert!(has(
+            r#"orderbook_book_levels{product="BTC-USD",venue="agg",side="bid"} 1"#
+        ));
+        assert!(has(
+            r#"orderbook_spread_bps{product="BTC-USD",venue="Coinbase"} 200.0"#
+        ));
+        assert!(has(
+            r#"orderbook_venue_fetch_duration_seconds_count{product="BTC-USD",venue="Coinbase"} 1"#
+        ));
+        assert!(text.ends_with("# EOF\n"));
+    }
+
+    #[tokio::test]
+    async fn test_book_gauges_dropped_without_book() {
+        let mut book = OrderBook::new(Exchange::Coinbase);
+        book.add_bid(99.0, 1.0);
+        book.add_ask(101.0, 1.0);
+        let coinbase = Arc::new(MockProvider::new("Coinbase", book.clone()));
+        let gemini = Arc::new(MockProvider::new("Gemini", book));
+        let metrics = Arc::new(Metrics::new());
+        let aggregator =
+            OrderBookAggregator::new(vec![coinbase.clone(), gemini.clone()], Product::BTCUSD)
+                .with_metrics(Arc::clone(&metrics));
+        aggregator.fetch_and_aggregate_data().await.unwrap();
+        let gemini_spread = r#"orderbook_spread_bps{product="BTC-USD",venue="Gemini"} 200.0"#;
+        assert!(metrics.encode().lines().any(|l| l == gemini_spread));
+
+        // Gemini fails, its series go while Coinbase keeps exporting
+        let error = || AggregatorError::RateLimitExceeded("Rate limit exceeded".to_string());
+        gemini.fail_next(error());
+        aggregator.fetch_and_aggregate_data().await.unwrap();
+        let text = metrics.encode();
+        assert!(!text.contains(r#"venue="Gemini",side="#));
+        assert!(!text.contains(gemini_spread));
+        assert!(text.contains(r#"orderbook_spread_bps{product="BTC-USD",venue="Coinbase"}"#));
+
+        // A failed round drops the consolidated book and every venue
+        coinbase.fail_next(error());
+        gemini.fail_next(error());
+        assert!(aggregator.fetch_and_aggregate_data().await.is_err());
+        let text = metrics.encode();
+        assert!(!text.contains("orderbook_book_levels{"));
+        assert!(!text.contains("orderbook_spread_bps{"));
+    }
+}
@@ -1,26 +1,378 @@
-use crate::{error::AggregatorError, types::Exchange};
+use crate::{
+    error::AggregatorError,
+    types::{Exchange, Side, unix_time_ms},
+};
 use ordered_float::OrderedFloat;
-use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize, Serializer, ser::SerializeStruct};
+use std::{collections::BTreeMap, fmt, str::FromStr};
 
-#[derive(Debug, Clone)]
+// JSON schema, prices and sizes are decimal strings so no precision is lost:
+//
+// Level / OrderDetails: {"price": "103123.79", "quantity": "0.1425", "exchange": "coinbase"}
+// A level quoted by several venues of a merged book also lists their quantities:
+//     "venues": [{"exchange": "coinbase", "quantity": "0.1"}, ...]
+// OrderBook: {
+//     "exchange": "coinbase" | "gemini" | "agg",
+//     "sequence": 123456 | null,
+//     "exchange_timestamp_ms": 1700000000000 | null,
+//     "received_at_ms": 1700000000123,
+//     "bids": [Level, ...],  best (highest) first
+//     "asks": [Level, ...]   best (lowest) first
+// }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Level {
-    price: OrderedFloat<f64>,
-    quantity: OrderedFloat<f64>,
-    exchnage: Exchange,
+    #[serde(with = "crate::decimal_string")]
+    pub price: OrderedFloat<f64>,
+    #[serde(with = "crate::decimal_string")]
+    pub quantity: OrderedFloat<f64>,
+    // First venue quoting the price
+    pub exchange: Exchange,
+    // Quantity of every venue quoting the price, in merge order. Only set when more than one
+    // venue does, otherwise `quantity` belongs to `exchange` alone.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub venues: Vec<VenueQuantity>,
+}
+
+// Quantity one venue quotes at the price of a merged level
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VenueQuantity {
+    pub exchange: Exchange,
+    #[serde(with = "crate::decimal_string")]
+    pub quantity: f64,
 }
 
-#[derive(Debug, Clone)]
+impl Level {
+    // Quantity of each venue quoting the price
+    pub fn venue_quantities(&self) -> impl Iterator<Item = VenueQuantity> + '_ {
+        let single = self.venues.is_empty().then_some(VenueQuantity {
+            exchange: self.exchange,
+            quantity: self.quantity.0,
+        });
+        self.venues.iter().copied().chain(single)
+    }
+
+    // Add the quantity of `other`, a level at the same price, keeping track of its venues
+    fn add(&mut self, other: &Level) {
+        if self.venues.is_empty() && other.venues.is_empty() && self.exchange == other.exchange {
+            *self.quantity += *other.quantity;
+            return;
+        }
+        let mut venues: Vec<VenueQuantity> = self.venue_quantities().collect();
+        for theirs in other.venue_quantities() {
+            match venues
+                .iter_mut()
+                .find(|venue| venue.exchange == theirs.exchange)
+            {
+                Some(venue) => venue.quantity += theirs.quantity,
+                None => venues.push(theirs),
+            }
+        }
+        *self.quantity += *other.quantity;
+        self.venues = venues;
+    }
+
+    // Take `quantity` off the level, from every venue in proportion to its quantity
+    fn reduce(&mut self, quantity: f64) {
+        if self.quantity.0 > 0.0 {
+            let ratio = (self.quantity.0 - quantity).max(0.0) / self.quantity.0;
+            for venue in &mut self.venues {
+                venue.quantity *= ratio;
+            }
+        }
+        *self.quantity -= quantity;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OrderDetails {
-    price: f64,
-    quantity: f64,
-    exchange: String,
+    #[serde(with = "crate::decimal_string")]
+    pub price: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub quantity: f64,
+    pub exchange: String,
+}
+
+// Totals of a sweep over one or more levels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FillSummary {
+    #[serde(with = "crate::decimal_string")]
+    pub quantity: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub notional: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub average_price: f64,
+}
+
+impl FillSummary {
+    pub fn from_fills(fills: &[OrderDetails]) -> Self {
+        let quantity: f64 = fills.iter().map(|fill| fill.quantity).sum();
+        let notional: f64 = fills.iter().map(|fill| fill.price * fill.quantity).sum();
+        let average_price = if quantity > 0.0 {
+            notional / quantity
+        } else {
+            0.0
+        };
+        FillSummary {
+            quantity,
+            notional,
+            average_price,
+        }
+    }
+
+    // Cost of the average price against `mid` in basis points, positive when worse than mid
+    pub fn slippage_bps(&self, side: Side, mid: f64) -> f64 {
+        let slippage = match side {
+            Side::Buy => self.average_price - mid,
+            Side::Sell => mid - self.average_price,
+        };
+        slippage / mid * 10_000.0
+    }
+}
+
+// Sizes priced by an impact curve when none are given, in base units
+pub const DEFAULT_IMPACT_SIZES: [f64; 4] = [1.0, 5.0, 10.0, 50.0];
+
+// Part of a sweep filled on one venue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VenueFill {
+    pub exchange: Exchange,
+    #[serde(with = "crate::decimal_string")]
+    pub quantity: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub notional: f64,
+}
+
+// Cost of sweeping one size off one side of the book
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImpactPoint {
+    #[serde(with = "crate::decimal_string")]
+    pub size: f64,
+    #[serde(flatten)]
+    pub summary: FillSummary,
+    // Price of the last level reached
+    #[serde(with = "crate::decimal_string")]
+    pub worst_price: f64,
+    pub slippage_bps: f64,
+    // False when the side holds less than `size`, the point then sweeps the whole side
+    pub complete: bool,
+    pub venues: Vec<VenueFill>,
+}
+
+// VWAP and slippage against mid by size, for buying and for selling
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImpactCurve {
+    #[serde(with = "crate::decimal_string")]
+    pub mid: f64,
+    pub buy: Vec<ImpactPoint>,
+    pub sell: Vec<ImpactPoint>,
+}
+
+// Width of the price buckets of a bucketed book view
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BucketWidth {
+    // Fixed price step, buckets sit on multiples of it, e.g. 10 for $10 buckets
+    Price(f64),
+    // Basis points of mid, buckets sit at whole multiples of it away from mid
+    Bps(f64),
 }
 
-#[derive(Debug, Clone)]
+impl FromStr for BucketWidth {
+    type Err = AggregatorError;
+
+    // "10" is a price step, "1bp" or "2.5bps" a width in basis points
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || AggregatorError::InvalidBucketWidth(s.to_string());
+        let trimmed = s.trim();
+        let (value, bps) = match trimmed
+            .strip_suffix("bps")
+            .or_else(|| trimmed.strip_suffix("bp"))
+        {
+            Some(value) => (value, true),
+            None => (trimmed, false),
+        };
+        let value: f64 = value.trim().parse().map_err(|_| invalid())?;
+        if !(value.is_finite() && value > 0.0) {
+            return Err(invalid());
+        }
+        Ok(if bps {
+            BucketWidth::Bps(value)
+        } else {
+            BucketWidth::Price(value)
+        })
+    }
+}
+
+impl fmt::Display for BucketWidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BucketWidth::Price(step) => write!(f, "{}", step),
+            BucketWidth::Bps(bps) => write!(f, "{}bp", bps),
+        }
+    }
+}
+
+// Levels of one side falling into the same price bucket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bucket {
+    // Outer edge of the bucket: lowest price of a bid bucket, highest of an ask bucket
+    #[serde(with = "crate::decimal_string")]
+    pub price: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub quantity: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub notional: f64,
+    pub levels: usize,
+    pub exchanges: Vec<VenueFill>,
+}
+
+// Book aggregated into price buckets, best bucket first on both sides
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BucketedBook {
+    pub exchange: Exchange,
+    pub received_at_ms: u64,
+    #[serde(serialize_with = "crate::decimal_string::serialize")]
+    pub width: BucketWidth,
+    pub bids: Vec<Bucket>,
+    pub asks: Vec<Bucket>,
+}
+
+// Change of one price level between two versions of a book, quantity 0 removes the level
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelChange {
+    #[serde(with = "crate::decimal_string")]
+    pub price: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub quantity: f64,
+    pub exchange: Exchange,
+    // Quantity of every venue quoting the price, set like `Level::venues`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub venues: Vec<VenueQuantity>,
+}
+
+// Level changes turning one version of a book into the next
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BookDiff {
+    pub bids: Vec<LevelChange>,
+    pub asks: Vec<LevelChange>,
+}
+
+impl BookDiff {
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+}
+
+// Changed and added levels of `new`, followed by removals of levels only in `old`
+fn diff_side(
+    old: &BTreeMap<OrderedFloat<f64>, Level>,
+    new: &BTreeMap<OrderedFloat<f64>, Level>,
+) -> Vec<LevelChange> {
+    let mut changes: Vec<LevelChange> = new
+        .iter()
+        .filter(|(price, level)| {
+            old.get(*price).is_none_or(|previous| {
+                previous.quantity != level.quantity
+                    || previous.exchange != level.exchange
+                    || previous.venues != level.venues
+            })
+        })
+        .map(|(price, level)| LevelChange {
+            price: price.0,
+            quantity: level.quantity.0,
+            exchange: level.exchange,
+            venues: level.venues.clone(),
+        })
+        .collect();
+    changes.extend(
+        old.iter()
+            .filter(|(price, _)| !new.contains_key(*price))
+            .map(|(price, level)| LevelChange {
+                price: price.0,
+                quantity: 0.0,
+                exchange: level.exchange,
+                venues: Vec::new(),
+            }),
+    );
+    changes
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "OrderBookRepr")]
 pub struct OrderBook {
     pub bids: BTreeMap<OrderedFloat<f64>, Level>,
     pub asks: BTreeMap<OrderedFloat<f64>, Level>,
     pub exchange: Exchange,
+    // Exchange sequence number of this snapshot, if the venue provides one
+    pub sequence: Option<u64>,
+    // Exchange timestamp of this snapshot in unix milliseconds, if the venue provides one
+    pub exchange_timestamp_ms: Option<u64>,
+    // Local time this snapshot was received in unix milliseconds
+    pub received_at_ms: u64,
+}
+
+// Serializes levels best first, see the schema at the top of this file
+impl Serialize for OrderBook {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut book = serializer.serialize_struct("OrderBook", 6)?;
+        book.serialize_field("exchange", &self.exchange)?;
+        book.serialize_field("sequence", &self.sequence)?;
+        book.serialize_field("exchange_timestamp_ms", &self.exchange_timestamp_ms)?;
+        book.serialize_field("received_at_ms", &self.received_at_ms)?;
+        book.serialize_field("bids", &LevelSeq(self.bids.values().rev()))?;
+        book.serialize_field("asks", &LevelSeq(self.asks.values()))?;
+        book.end()
+    }
+}
+
+struct LevelSeq<I>(I);
+
+impl<'a, I> Serialize for LevelSeq<I>
+where
+    I: Iterator<Item = &'a Level> + Clone,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.clone())
+    }
+}
+
+// Wire form of an OrderBook, levels as lists instead of price keyed maps
+#[derive(Deserialize)]
+struct OrderBookRepr {
+    exchange: Exchange,
+    #[serde(default)]
+    sequence: Option<u64>,
+    #[serde(default)]
+    exchange_timestamp_ms: Option<u64>,
+    received_at_ms: u64,
+    bids: Vec<Level>,
+    asks: Vec<Level>,
+}
+
+impl From<OrderBookRepr> for OrderBook {
+    fn from(repr: OrderBookRepr) -> Self {
+        // Repeated prices are summed, as add_bid and add_ask do
+        let to_map = |levels: Vec<Level>| {
+            let mut map: BTreeMap<OrderedFloat<f64>, Level> = BTreeMap::new();
+            for level in levels {
+                match map.get_mut(&level.price) {
+                    Some(existing) => existing.add(&level),
+                    None => {
+                        map.insert(level.price, level);
+                    }
+                }
+            }
+            map
+        };
+        OrderBook {
+            bids: to_map(repr.bids),
+            asks: to_map(repr.asks),
+            exchange: repr.exchange,
+            sequence: repr.sequence,
+            exchange_timestamp_ms: repr.exchange_timestamp_ms,
+            received_at_ms: repr.received_at_ms,
+        }
+    }
 }
 
 impl OrderBook {
@@ -30,25 +382,139 @@ impl OrderBook {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
             exchange,
+            sequence: None,
+            exchange_timestamp_ms: None,
+            received_at_ms: unix_time_ms(),
         }
     }
 
+    // Age of the snapshot at `now_ms`, preferring the exchange timestamp over the receive time
+    pub fn age_ms(&self, now_ms: u64) -> u64 {
+        now_ms.saturating_sub(self.exchange_timestamp_ms.unwrap_or(self.received_at_ms))
+    }
+
     pub fn is_empty(&self) -> bool {
         self.bids.is_empty() && self.asks.is_empty()
     }
 
+    // Highest bid level, if any
+    pub fn best_bid(&self) -> Option<&Level> {
+        self.bids.values().next_back()
+    }
+
+    // Lowest ask level, if any
+    pub fn best_ask(&self) -> Option<&Level> {
+        self.asks.values().next()
+    }
+
+    // Midpoint of the best bid and best ask, if both sides have levels
+    pub fn mid_price(&self) -> Option<f64> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => Some((bid.price.0 + ask.price.0) / 2.0),
+            _ => None,
+        }
+    }
+
+    // True when the best bid is at or above the best ask
+    pub fn is_crossed(&self) -> bool {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => bid.price >= ask.price,
+            _ => false,
+        }
+    }
+
+    // Copy of the book holding only the best `depth` levels of each side
+    pub fn top_n(&self, depth: usize) -> OrderBook {
+        OrderBook {
+            bids: self
+                .bids
+                .iter()
+                .rev()
+                .take(depth)
+                .map(|(price, level)| (*price, level.clone()))
+                .collect(),
+            asks: self
+                .asks
+                .iter()
+                .take(depth)
+                .map(|(price, level)| (*price, level.clone()))
+                .collect(),
+            exchange: self.exchange,
+            sequence: self.sequence,
+            exchange_timestamp_ms: self.exchange_timestamp_ms,
+            received_at_ms: self.received_at_ms,
+        }
+    }
+
+    // Levels grouped into buckets of `width`, the best `depth` buckets per side, with the
+    // quantity of each venue in every bucket
+    pub fn bucketed(&self, width: BucketWidth, depth: usize) -> BucketedBook {
+        let (anchor, step) = match width {
+            BucketWidth::Price(step) => (0.0, step),
+            BucketWidth::Bps(bps) => {
+                let anchor = self
+                    .mid_price()
+                    .or_else(|| {
+                        self.best_bid()
+                            .or(self.best_ask())
+                            .map(|level| level.price.0)
+                    })
+                    .unwrap_or_default();
+                (anchor, anchor * bps / 10_000.0)
+            }
+        };
+        BucketedBook {
+            exchange: self.exchange,
+            received_at_ms: self.received_at_ms,
+            width,
+            // Bids round down and asks round up, so a bucket never looks better than its levels
+            bids: bucket_side(self.bids.values().rev(), depth, |price| {
+                anchor + ((price - anchor) / step).floor() * step
+            }),
+            asks: bucket_side(self.asks.values(), depth, |price| {
+                anchor + ((price - anchor) / step).ceil() * step
+            }),
+        }
+    }
+
+    // Level changes that turn `previous` into this book
+    pub fn diff(&self, previous: &OrderBook) -> BookDiff {
+        BookDiff {
+            bids: diff_side(&previous.bids, &self.bids),
+            asks: diff_side(&previous.asks, &self.asks),
+        }
+    }
+
+    // Match crossed or locked levels against each other until the best bid is below the best ask
+    pub fn uncross(&mut self) {
+        while let (Some(mut bid), Some(mut ask)) = (self.bids.last_entry(), self.asks.first_entry())
+        {
+            if bid.key() < ask.key() {
+                break;
+            }
+            let matched = bid.get().quantity.min(ask.get().quantity).0;
+            bid.get_mut().reduce(matched);
+            ask.get_mut().reduce(matched);
+            if *bid.get().quantity <= 0.0 {
+                bid.remove();
+            }
+            if *ask.get().quantity <= 0.0 {
+                ask.remove();
+            }
+        }
+    }
+
     // Add a bid level to the order book
     pub fn add_bid(&mut self, price: f64, quantity: f64) {
         let level = Level {
             price: OrderedFloat(price),
-            exchnage: self.exchange,
+            exchange: self.exchange,
             quantity: OrderedFloat(quantity),
+            venues: Vec::new(),
         };
 
         match self.bids.get_mut(&OrderedFloat(price)) {
-            Some(value) => {
-                *value.quantity = *value.quantity + *level.quantity;
-            }
+            Some(value) => value.add(&level),
             None => {
                 self.bids.insert(OrderedFloat(price), level);
             }
@@ -59,14 +525,13 @@ impl OrderBook {
     pub fn add_ask(&mut self, price: f64, quantity: f64) {
         let level = Level {
             price: OrderedFloat(price),
-            exchnage: self.exchange,
+            exchange: self.exchange,
             quantity: OrderedFloat(quantity),
+            venues: Vec::new(),
         };
 
         match self.asks.get_mut(&OrderedFloat(price)) {
-            Some(value) => {
-                *value.quantity = *value.quantity + *level.quantity;
-            }
+            Some(value) => value.add(&level),
             None => {
                 self.asks.insert(OrderedFloat(price), level);
             }
@@ -74,11 +539,18 @@ impl OrderBook {
     }
 
     pub fn merge(&mut self, other: &OrderBook) {
+        // A merged book is only as fresh as its oldest input
+        self.received_at_ms = self.received_at_ms.min(other.received_at_ms);
+        self.exchange_timestamp_ms = match (self.exchange_timestamp_ms, other.exchange_timestamp_ms)
+        {
+            (Some(ours), Some(theirs)) => Some(ours.min(theirs)),
+            (ours, theirs) => ours.or(theirs),
+        };
+        // Sequence numbers are per venue and meaningless once books are combined
+        self.sequence = None;
         for (price, level) in &other.bids {
             match self.bids.get_mut(price) {
-                Some(value) => {
-                    *value.quantity = *value.quantity + *level.quantity;
-                }
+                Some(value) => value.add(level),
                 None => {
                     self.bids.insert(*price, level.clone());
                 }
@@ -86,9 +558,7 @@ impl OrderBook {
         }
         for (price, level) in &other.asks {
             match self.asks.get_mut(price) {
-                Some(value) => {
-                    *value.quantity = *value.quantity + *level.quantity;
-                }
+                Some(value) => value.add(level),
                 None => {
                     self.asks.insert(*price, level.clone());
                 }
@@ -96,28 +566,88 @@ impl OrderBook {
         }
     }
 
+    // Sweep the asks for a buy or the bids for a sell
+    pub fn calculate_best_offer(
+        &self,
+        side: Side,
+        quantity: f64,
+    ) -> Result<Vec<OrderDetails>, AggregatorError> {
+        match side {
+            Side::Buy => self.calculate_best_buy_offer(quantity),
+            Side::Sell => self.calculate_best_sell_offer(quantity),
+        }
+    }
+
+    // Sweep the asks until `notional` is spent on a buy, or the bids until it is received on a sell
+    pub fn calculate_best_offer_for_notional(
+        &self,
+        side: Side,
+        notional: f64,
+    ) -> Result<Vec<OrderDetails>, AggregatorError> {
+        let levels: Box<dyn Iterator<Item = &Level>> = match side {
+            Side::Buy => Box::new(self.asks.values()),
+            Side::Sell => Box::new(self.bids.values().rev()),
+        };
+        let mut remaining = notional;
+        let mut fills = Vec::new();
+        for level in levels {
+            if remaining <= 0.0 {
+                break;
+            }
+            let level_notional = level.price.0 * level.quantity.0;
+            let quantity = if level_notional >= remaining {
+                // Set to zero rather than subtracting to avoid a rounding leftover
+                let quantity = remaining / level.price.0;
+                remaining = 0.0;
+                quantity
+            } else {
+                remaining -= level_notional;
+                level.quantity.0
+            };
+            fill_level(level, quantity, &mut fills);
+        }
+
+        if remaining > 0.0 {
+            return Err(AggregatorError::InsufficientLiquidity(
+                "Insufficient liquidity to complete order".to_string(),
+            ));
+        }
+        Ok(fills)
+    }
+
+    // Impact curve of both sides at every positive size, smallest first. Each side is walked
+    // once, every point extending the sweep of the previous one.
+    pub fn impact_curve(&self, sizes: &[f64]) -> Result<ImpactCurve, AggregatorError> {
+        let mid = self.mid_price().ok_or_else(|| {
+            AggregatorError::InsufficientLiquidity(
+                "No two sided book to measure impact".to_string(),
+            )
+        })?;
+        let mut sizes: Vec<f64> = sizes.iter().copied().filter(|size| *size > 0.0).collect();
+        sizes.sort_by(f64::total_cmp);
+        sizes.dedup();
+        Ok(ImpactCurve {
+            mid,
+            buy: impact_side(self.asks.values(), Side::Buy, &sizes, mid),
+            sell: impact_side(self.bids.values().rev(), Side::Sell, &sizes, mid),
+        })
+    }
+
     pub fn calculate_best_buy_offer(
         &self,
         quantity: f64,
     ) -> Result<Vec<OrderDetails>, AggregatorError> {
         let mut remaining = quantity;
-        let mut total_cost = 0.0;
         let mut order_fullfilment = Vec::new();
         // Iterate through asks lowest first
-        for (price, level) in &self.asks {
+        for level in self.asks.values() {
             if remaining <= 0.0 {
                 break;
             }
             let qty_to_buy = remaining.min(level.quantity.0);
 
-            total_cost += qty_to_buy * price.0;
             remaining -= qty_to_buy;
-            let order_fullfilment_details = OrderDetails {
-                price: price.0,
-                quantity: qty_to_buy,
-                exchange: level.exchnage.to_string(),
-            };
-            order_fullfilment.push(order_fullfilment_details)
+            fill_level(level, qty_to_buy, &mut order_fullfilment);
         }
 
         if remaining > 0.0 {
@@ -125,7 +655,6 @@ impl OrderBook {
                 "Insufficient liquidity to complete order".to_string(),
             ));
         }
-        total_cost = (total_cost * 100.0).round() / 100.0;
         Ok(order_fullfilment)
     }
 
@@ -134,23 +663,16 @@ impl OrderBook {
         quantity: f64,
     ) -> Result<Vec<OrderDetails>, AggregatorError> {
         let mut remaining = quantity;
-        let mut total_cost = 0.0;
         let mut order_fullfilment = Vec::new();
         // Iterate through bids highest first
-        for (price, level) in self.bids.iter().rev() {
+        for level in self.bids.values().rev() {
             if remaining <= 0.0 {
                 break;
             }
 
             let qty_to_sell = remaining.min(level.quantity.0);
-            total_cost += qty_to_sell * price.0;
             remaining -= qty_to_sell;
-            let order_fullfilment_details = OrderDetails {
-                price: price.0,
-                quantity: qty_to_sell,
-                exchange: level.exchnage.to_string(),
-            };
-            order_fullfilment.push(order_fullfilment_details)
+            fill_level(level, qty_to_sell, &mut order_fullfilment);
         }
 
         if remaining > 0.0 {
@@ -158,11 +680,164 @@ impl OrderBook {
                 "Insufficient liquidity to complete order".to_string(),
             ));
         }
-        total_cost = (total_cost * 100.0).round() / 100.0;
         Ok(order_fullfilment)
     }
 }
 
+// Fill `quantity` off `level`, one fill per venue quoting it, each in proportion to its quantity
+fn fill_level(level: &Level, quantity: f64, fills: &mut Vec<OrderDetails>) {
+    if level.venues.is_empty() {
+        fills.push(OrderDetails {
+            price: level.price.0,
+            quantity,
+            exchange: level.exchange.to_string(),
+        });
+        return;
+    }
+    fills.extend(level.venues.iter().map(|venue| OrderDetails {
+        price: level.price.0,
+        quantity: quantity * venue.quantity / level.quantity.0,
+        exchange: venue.exchange.to_string(),
+    }));
+}
+
+// Group `levels`, best first, by bucket price until `depth` buckets are complete
+fn bucket_side<'a>(
+    levels: impl Iterator<Item = &'a Level>,
+    depth: usize,
+    bucket_price: impl Fn(f64) -> f64,
+) -> Vec<Bucket> {
+    let mut buckets: Vec<Bucket> = Vec::new();
+    for level in levels {
+        let price = bucket_price(level.price.0);
+        let (quantity, notional) = (level.quantity.0, level.quantity.0 * level.price.0);
+        let bucket = match buckets.last_mut() {
+            Some(bucket) if bucket.price == price => bucket,
+            _ => {
+                if buckets.len() == depth {
+                    break;
+                }
+                buckets.push(Bucket {
+                    price,
+                    quantity: 0.0,
+                    notional: 0.0,
+                    levels: 0,
+                    exchanges: Vec::new(),
+                });
+                buckets.last_mut().expect("bucket just pushed")
+            }
+        };
+        bucket.quantity += quantity;
+        bucket.notional += notional;
+        bucket.levels += 1;
+        for venue_quantity in level.venue_quantities() {
+            let (quantity, notional) = (
+                venue_quantity.quantity,
+                venue_quantity.quantity * level.price.0,
+            );
+            match bucket
+                .exchanges
+                .iter_mut()
+                .find(|venue| venue.exchange == venue_quantity.exchange)
+            {
+                Some(venue) => {
+                    venue.quantity += quantity;
+                    venue.notional += notional;
+                }
+                None => bucket.exchanges.push(VenueFill {
+                    exchange: venue_quantity.exchange,
+                    quantity,
+                    notional,
+                }),
+            }
+        }
+    }
+    buckets
+}
+
+// Sweep `levels`, best first, recording a point each time the filled quantity reaches a size
+fn impact_side<'a>(
+    levels: impl Iterator<Item = &'a Level>,
+    side: Side,
+    sizes: &[f64],
+    mid: f64,
+) -> Vec<ImpactPoint> {
+    let mut points = Vec::with_capacity(sizes.len());
+    let mut summary = FillSummary {
+        quantity: 0.0,
+        notional: 0.0,
+        average_price: 0.0,
+    };
+    let mut worst_price = 0.0;
+    let mut venues: Vec<VenueFill> = Vec::new();
+    let mut sizes = sizes.iter().copied().peekable();
+
+    let point = |size: f64, summary: FillSummary, worst_price: f64, venues: &[VenueFill]| {
+        let summary = FillSummary {
+            average_price: summary.notional / summary.quantity,
+            ..summary
+        };
+        ImpactPoint {
+            size,
+            summary,
+            worst_price,
+            slippage_bps: summary.slippage_bps(side, mid),
+            complete: summary.quantity >= size,
+            venues: venues.to_vec(),
+        }
+    };
+
+    for level in levels {
+        let mut available = level.quantity.0;
+        while available > 0.0 {
+            let Some(&size) = sizes.peek() else {
+                break;
+            };
+            let wanted = size - summary.quantity;
+            let quantity = wanted.min(available);
+            available -= quantity;
+            summary.notional += quantity * level.price.0;
+            // Set to the size rather than adding to avoid a rounding leftover
+            summary.quantity = if quantity == wanted {
+                size
+            } else {
+                summary.quantity + quantity
+            };
+            worst_price = level.price.0;
+            // A partly swept level fills every venue quoting it in proportion
+            for venue_quantity in level.venue_quantities() {
+                let filled = quantity * venue_quantity.quantity / level.quantity.0;
+                match venues
+                    .iter_mut()
+                    .find(|venue| venue.exchange == venue_quantity.exchange)
+                {
+                    Some(venue) => {
+                        venue.quantity += filled;
+                        venue.notional += filled * level.price.0;
+                    }
+                    None => venues.push(VenueFill {
+                        exchange: venue_quantity.exchange,
+                        quantity: filled,
+                        notional: filled * level.price.0,
+                    }),
+                }
+            }
+            if summary.quantity >= size {
+                points.push(point(size, summary, worst_price, &venues));
+                sizes.next();
+            }
+        }
+        if sizes.peek().is_none() {
+            break;
+        }
+    }
+    // Sizes beyond the depth of the side
+    for size in sizes {
+        points.push(point(size, summary, worst_price, &venues));
+    }
+    points
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -180,6 +855,9 @@ mod tests {
         // Min ask price is 103123.79/BTC with quantity of 0.1425 BTC
         // So total cost = 0.1 * 103123.79 = 10312.379
         // Rounded to 2 decimal places = 10312.38
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].price, 103123.79);
+        assert_eq!(res[0].quantity, 0.1);
     }
 
     #[test]
@@ -192,7 +870,311 @@ mod tests {
         let res = order_book.calculate_best_sell_offer(0.4).unwrap();
         // Max bid price is 103120.00/BTC with quantity of 0.5 BTC
         // We can buy 0.4 BTC at total cost = (0.4 * 103120.00) =  41248.00
-        // assert_eq!(res, 41248.00);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].price * res[0].quantity, 41248.00);
+    }
+
+    #[test]
+    fn test_fill_summary() {
+        let mut order_book = OrderBook::new(Exchange::Coinbase);
+        order_book.add_ask(100.0, 1.0);
+        order_book.add_ask(102.0, 1.0);
+        let fills = order_book.calculate_best_offer(Side::Buy, 1.5).unwrap();
+        let summary = FillSummary::from_fills(&fills);
+        assert_eq!(summary.quantity, 1.5);
+        assert_eq!(summary.notional, 151.0);
+        assert!((summary.average_price - 100.666_666).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_best_offer_for_notional() {
+        let mut order_book = OrderBook::new(Exchange::Coinbase);
+        order_book.add_ask(100.0, 1.0);
+        order_book.add_ask(200.0, 1.0);
+        order_book.add_bid(50.0, 2.0);
+
+        // 100 on the first level, the remaining 100 buys half of the second
+        let fills = order_book
+            .calculate_best_offer_for_notional(Side::Buy, 200.0)
+            .unwrap();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[1].quantity, 0.5);
+        assert_eq!(FillSummary::from_fills(&fills).notional, 200.0);
+
+        assert!(matches!(
+            order_book.calculate_best_offer_for_notional(Side::Sell, 101.0),
+            Err(AggregatorError::InsufficientLiquidity(_))
+        ));
+    }
+
+    #[test]
+    fn test_impact_curve() {
+        let mut order_book = OrderBook::new(Exchange::AggregatedExchange);
+        order_book.add_bid(99.0, 1.0);
+        let mut gemini = OrderBook::new(Exchange::Gemini);
+        gemini.add_ask(101.0, 1.0);
+        gemini.add_ask(102.0, 2.0);
+        let mut coinbase = OrderBook::new(Exchange::Coinbase);
+        coinbase.add_ask(102.0, 2.0);
+        order_book.merge(&gemini);
+        order_book.merge(&coinbase);
+
+        let curve = order_book.impact_curve(&[3.0, 1.0, 0.0, 2.0]).unwrap();
+        assert_eq!(curve.mid, 100.0);
+        let sizes: Vec<f64> = curve.buy.iter().map(|point| point.size).collect();
+        assert_eq!(sizes, vec![1.0, 2.0, 3.0]);
+        // 1 @ 101 on Gemini, then 2 of the 4 both venues quote at 102, half on each
+        let point = &curve.buy[2];
+        assert_eq!(point.summary.notional, 305.0);
+        assert_eq!(point.worst_price, 102.0);
+        assert!((point.slippage_bps - (305.0 / 3.0 - 100.0) / 100.0 * 10_000.0).abs() < 1e-9);
+        assert_eq!(point.venues.len(), 2);
+        assert_eq!(point.venues[0].quantity, 2.0);
+        assert_eq!(point.venues[0].notional, 203.0);
+        assert_eq!(point.venues[1].exchange, Exchange::Coinbase);
+        assert_eq!(point.venues[1].quantity, 1.0);
+        assert_eq!(
+            point.summary,
+            FillSummary::from_fills(&order_book.calculate_best_buy_offer(3.0).unwrap())
+        );
+
+        // Only 1 BTC of bids, larger sizes sweep the whole side
+        assert!(curve.sell[0].complete);
+        assert_eq!(curve.sell[0].slippage_bps, 100.0);
+        assert!(!curve.sell[2].complete);
+        assert_eq!(curve.sell[2].summary.quantity, 1.0);
+    }
+
+    #[test]
+    fn test_bucketed() {
+        let mut order_book = OrderBook::new(Exchange::AggregatedExchange);
+        let mut coinbase = OrderBook::new(Exchange::Coinbase);
+        coinbase.add_bid(1_009.0, 1.0);
+        coinbase.add_bid(1_001.0, 1.0);
+        coinbase.add_ask(1_011.0, 2.0);
+        let mut gemini = OrderBook::new(Exchange::Gemini);
+        gemini.add_bid(1_000.0, 3.0);
+        gemini.add_bid(985.0, 1.0);
+        gemini.add_ask(1_020.5, 1.0);
+        // Both venues quote 1009
+        gemini.add_bid(1_009.0, 2.0);
+        order_book.merge(&coinbase);
+        order_book.merge(&gemini);
+
+        let book = order_book.bucketed("10".parse().unwrap(), 2);
+        // 1009, 1001 and 1000 round down into the 1000 bucket, 985 into 980
+        assert_eq!(book.bids.len(), 2);
+        assert_eq!(book.bids[1].price, 980.0);
+        let bucket = &book.bids[0];
+        assert_eq!(
+            (bucket.price, bucket.quantity, bucket.levels),
+            (1_000.0, 7.0, 3)
+        );
+        assert_eq!(bucket.exchanges[0].exchange, Exchange::Coinbase);
+        assert_eq!(bucket.exchanges[0].quantity, 2.0);
+        assert_eq!(bucket.exchanges[1].quantity, 5.0);
+        assert_eq!(bucket.exchanges[1].notional, 2.0 * 1_009.0 + 3.0 * 1_000.0);
+        // Asks round up
+        assert_eq!(book.asks[0].price, 1_020.0);
+        assert_eq!(book.asks[0].quantity, 2.0);
+        assert_eq!(book.asks[1].price, 1_030.0);
+
+        let book = order_book.bucketed(BucketWidth::Bps(100.0), 1);
+        // Mid 1010, 1% buckets: bids above 999.9 and asks up to 1020.1
+        assert_eq!(book.bids[0].quantity, 7.0);
+        assert_eq!(book.asks[0].quantity, 2.0);
+        assert_eq!(book.asks.len(), 1);
+
+        assert_eq!(
+            "2.5bps".parse::<BucketWidth>().unwrap(),
+            BucketWidth::Bps(2.5)
+        );
+        assert!("-1".parse::<BucketWidth>().is_err());
+        let json = serde_json::to_value(&book).unwrap();
+        assert_eq!(json["width"], "100bp");
+        assert_eq!(json["bids"][0]["exchanges"][1]["exchange"], "gemini");
+    }
+
+    #[test]
+    fn test_merge_keeps_oldest_timestamp() {
+        let mut coinbase = OrderBook::new(Exchange::Coinbase);
+        coinbase.sequence = Some(42);
+        coinbase.received_at_ms = 2_000;
+        let mut gemini = OrderBook::new(Exchange::Gemini);
+        gemini.exchange_timestamp_ms = Some(1_000);
+        gemini.received_at_ms = 3_000;
+
+        coinbase.merge(&gemini);
+        assert_eq!(coinbase.sequence, None);
+        assert_eq!(coinbase.received_at_ms, 2_000);
+        assert_eq!(coinbase.exchange_timestamp_ms, Some(1_000));
+        // Exchange timestamp takes precedence when computing the age
+        assert_eq!(coinbase.age_ms(5_000), 4_000);
+    }
+
+    #[test]
+    fn test_uncross() {
+        let mut order_book = OrderBook::new(Exchange::AggregatedExchange);
+        order_book.add_bid(101.0, 1.0);
+        order_book.add_bid(100.0, 2.0);
+        order_book.add_ask(100.0, 0.5);
+        order_book.add_ask(100.5, 0.25);
+        order_book.add_ask(102.0, 1.0);
+        assert!(order_book.is_crossed());
+
+        order_book.uncross();
+        assert!(!order_book.is_crossed());
+        // 0.75 of the 101 bid was matched against both crossed asks
+        assert_eq!(order_book.best_bid().unwrap().price.0, 101.0);
+        assert_eq!(order_book.best_bid().unwrap().quantity.0, 0.25);
+        assert_eq!(order_book.best_ask().unwrap().price.0, 102.0);
+    }
+
+    #[test]
+    fn test_merge_keeps_venue_quantities() {
+        let mut coinbase = OrderBook::new(Exchange::Coinbase);
+        coinbase.add_bid(100.0, 1.0);
+        coinbase.add_ask(101.0, 1.0);
+        let mut gemini = OrderBook::new(Exchange::Gemini);
+        gemini.add_bid(100.0, 3.0);
+        gemini.add_bid(99.0, 1.0);
+        let mut order_book = OrderBook::new(Exchange::AggregatedExchange);
+        order_book.merge(&coinbase);
+        order_book.merge(&gemini);
+
+        let bid = order_book.best_bid().unwrap();
+        assert_eq!(bid.quantity.0, 4.0);
+        let venues: Vec<(Exchange, f64)> = bid
+            .venue_quantities()
+            .map(|venue| (venue.exchange, venue.quantity))
+            .collect();
+        assert_eq!(venues, [(Exchange::Coinbase, 1.0), (Exchange::Gemini, 3.0)]);
+        // Single venue levels list no venues
+        assert!(order_book.bids[&OrderedFloat(99.0)].venues.is_empty());
+        let json = serde_json::to_value(&order_book).unwrap();
+        assert_eq!(json["bids"][0]["venues"][1]["quantity"], "3");
+        assert!(json["bids"][1].get("venues").is_none());
+        let parsed: OrderBook = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.best_bid(), Some(bid));
+
+        // Uncrossing takes the matched quantity from both venues in proportion
+        order_book.add_ask(100.0, 2.0);
+        order_book.uncross();
+        let venues: Vec<f64> = order_book
+            .best_bid()
+            .unwrap()
+            .venue_quantities()
+            .map(|venue| venue.quantity)
+            .collect();
+        assert_eq!(venues, [0.5, 1.5]);
+    }
+
+    #[test]
+    fn test_top_n_and_diff() {
+        let mut previous = OrderBook::new(Exchange::Coinbase);
+        previous.add_bid(99.0, 1.0);
+        previous.add_bid(98.0, 1.0);
+        previous.add_bid(97.0, 1.0);
+        previous.add_ask(101.0, 1.0);
+        let previous = previous.top_n(2);
+        assert_eq!(previous.bids.len(), 2);
+        assert!(!previous.bids.contains_key(&OrderedFloat(97.0)));
+
+        let mut next = OrderBook::new(Exchange::Coinbase);
+        next.add_bid(99.0, 2.0);
+        next.add_bid(98.0, 1.0);
+        next.add_ask(100.5, 1.0);
+        let diff = next.diff(&previous);
+        assert_eq!(
+            diff.bids,
+            vec![LevelChange {
+                price: 99.0,
+                quantity: 2.0,
+                exchange: Exchange::Coinbase,
+                venues: Vec::new(),
+            }]
+        );
+        // New 100.5 ask, and the 101 ask removed
+        assert_eq!(diff.asks.len(), 2);
+        assert_eq!((diff.asks[0].price, diff.asks[0].quantity), (100.5, 1.0));
+        assert_eq!((diff.asks[1].price, diff.asks[1].quantity), (101.0, 0.0));
+        assert!(next.diff(&next).is_empty());
+
+        // Same total at 98, but now split between two venues
+        let mut shared = next.clone();
+        shared
+            .bids
+            .get_mut(&OrderedFloat(98.0))
+            .unwrap()
+            .reduce(0.5);
+        let mut gemini = OrderBook::new(Exchange::Gemini);
+        gemini.add_bid(98.0, 0.5);
+        shared.merge(&gemini);
+        let diff = shared.diff(&next);
+        assert_eq!(diff.bids.len(), 1);
+        assert_eq!(diff.bids[0].quantity, 1.0);
+        assert_eq!(diff.bids[0].venues.len(), 2);
+    }
+
+    #[test]
+    fn test_level_json() {
+        let level = Level {
+            price: OrderedFloat(103123.79),
+            quantity: OrderedFloat(0.1425),
+            exchange: Exchange::Gemini,
+            venues: Vec::new(),
+        };
+        let json = serde_json::to_string(&level).unwrap();
+        assert_eq!(
+            json,
+            r#"{"price":"103123.79","quantity":"0.1425","exchange":"gemini"}"#
+        );
+        assert_eq!(serde_json::from_str::<Level>(&json).unwrap(), level);
+        // Numbers are rejected, the schema only allows strings
+        assert!(
+            serde_json::from_str::<Level>(r#"{"price":1.0,"quantity":"1","exchange":"gemini"}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_order_details_round_trip() {
+        let mut order_book = OrderBook::new(Exchange::Coinbase);
+        order_book.add_ask(103123.79, 0.1425);
+        let fills = order_book.calculate_best_buy_offer(0.1).unwrap();
+        let json = serde_json::to_string(&fills).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"price":"103123.79","quantity":"0.1","exchange":"coinbase"}]"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Vec<OrderDetails>>(&json).unwrap(),
+            fills
+        );
+    }
+
+    #[test]
+    fn test_order_book_round_trip() {
+        let mut order_book = OrderBook::new(Exchange::AggregatedExchange);
+        order_book.add_bid(0.1 + 0.2, 1.0 / 3.0);
+        order_book.add_bid(0.2, 2.0);
+        order_book.add_ask(1e-9, 12_345_678.123_456_78);
+        order_book.sequence = Some(7);
+        order_book.received_at_ms = 1_700_000_000_000;
+
+        let json = serde_json::to_value(&order_book).unwrap();
+        assert_eq!(json["exchange"], "agg");
+        assert_eq!(json["exchange_timestamp_ms"], serde_json::Value::Null);
+        // Best bid first
+        assert_eq!(json["bids"][0]["price"], "0.30000000000000004");
+        assert_eq!(json["bids"][1]["price"], "0.2");
+
+        let decoded: OrderBook = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.bids, order_book.bids);
+        assert_eq!(decoded.asks, order_book.asks);
+        assert_eq!(decoded.exchange, order_book.exchange);
+        assert_eq!(decoded.sequence, Some(7));
+        assert_eq!(decoded.received_at_ms, 1_700_000_000_000);
     }
 
     #[test]
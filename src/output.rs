@@ -0,0 +1,997 @@
+use crate::{
+    aggregator::VenueReport,
+    analytics::{BookStats, MarketStats},
+    arbitrage::Opportunity,
+    backtest::BacktestResult,
+    error::AggregatorError,
+    order_book::{BucketedBook, FillSummary, ImpactCurve, OrderBook, OrderDetails, VenueFill},
+    planner::Plan,
+    types::{Product, Side},
+};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::Write;
+
+// How the command line prints quotes and books
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    // One JSON document, in the schema described in order_book.rs
+    Json,
+    // Header line followed by one row per fill or level
+    Csv,
+    // Aligned columns for reading in a terminal
+    #[default]
+    Table,
+}
+
+// Result of sweeping the book for one side
+#[derive(Debug, Clone, Serialize)]
+pub struct Quote {
+    pub side: Side,
+    #[serde(flatten)]
+    pub summary: FillSummary,
+    pub fills: Vec<OrderDetails>,
+}
+
+impl Quote {
+    pub fn new(side: Side, fills: Vec<OrderDetails>) -> Self {
+        Quote {
+            side,
+            summary: FillSummary::from_fills(&fills),
+            fills,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct QuotesJson<'a> {
+    product: String,
+    quotes: &'a [Quote],
+}
+
+#[derive(Serialize)]
+struct BacktestJson<'a> {
+    product: String,
+    results: &'a [BacktestResult],
+}
+
+#[derive(Serialize)]
+struct PlanJson<'a> {
+    product: String,
+    #[serde(flatten)]
+    plan: &'a Plan,
+}
+
+#[derive(Serialize)]
+struct ImpactJson<'a> {
+    product: String,
+    #[serde(flatten)]
+    curve: &'a ImpactCurve,
+}
+
+#[derive(Serialize)]
+struct StatsJson<'a> {
+    product: String,
+    #[serde(flatten)]
+    stats: &'a MarketStats,
+}
+
+#[derive(Serialize)]
+struct BucketsJson<'a> {
+    product: String,
+    #[serde(flatten)]
+    book: &'a BucketedBook,
+}
+
+#[derive(Serialize)]
+struct ArbitrageJson<'a> {
+    product: String,
+    opportunities: &'a [Opportunity],
+}
+
+#[derive(Serialize)]
+struct ArbitrageUpdateJson<'a> {
+    product: String,
+    #[serde(flatten)]
+    opportunity: &'a Opportunity,
+}
+
+#[derive(Serialize)]
+struct VenuesJson {
+    product: String,
+    venues: Vec<VenueJson>,
+}
+
+#[derive(Serialize)]
+struct VenueJson {
+    venue: String,
+    status: String,
+    latency_ms: u128,
+    bid_levels: usize,
+    ask_levels: usize,
+    error: Option<String>,
+    error_message: Option<String>,
+    // Set when the venue answered with an error status
+    http_status: Option<u16>,
+    retryable: bool,
+}
+
+#[derive(Serialize)]
+struct BookJson<'a> {
+    product: String,
+    #[serde(flatten)]
+    book: &'a OrderBook,
+}
+
+// Print the fills of every quote followed by its totals.
+// In csv and table form the totals row uses "total" as exchange and the average price as price.
+pub fn write_quotes(
+    out: &mut impl Write,
+    format: OutputFormat,
+    product: &Product,
+    quotes: &[Quote],
+) -> Result<(), AggregatorError> {
+    match format {
+        OutputFormat::Json => {
+            let json = QuotesJson {
+                product: product.to_string(),
+                quotes,
+            };
+            serde_json::to_writer_pretty(&mut *out, &json)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(out, "side,exchange,price,quantity,notional")?;
+            for quote in quotes {
+                for fill in &quote.fills {
+                    writeln!(
+                        out,
+                        "{},{},{},{},{}",
+                        quote.side,
+                        fill.exchange,
+                        fill.price,
+                        fill.quantity,
+                        fill.price * fill.quantity
+                    )?;
+                }
+                writeln!(
+                    out,
+                    "{},total,{},{},{}",
+                    quote.side,
+                    quote.summary.average_price,
+                    quote.summary.quantity,
+                    quote.summary.notional
+                )?;
+            }
+        }
+        OutputFormat::Table => {
+            writeln!(
+                out,
+                "{:<5} {:<9} {:>14} {:>14} {:>16}",
+                "SIDE", "EXCHANGE", "PRICE", "QUANTITY", "NOTIONAL"
+            )?;
+            for quote in quotes {
+                for fill in &quote.fills {
+                    writeln!(
+                        out,
+                        "{:<5} {:<9} {:>14.2} {:>14.8} {:>16.2}",
+                        quote.side,
+                        fill.exchange,
+                        fill.price,
+                        fill.quantity,
+                        fill.price * fill.quantity
+                    )?;
+                }
+                writeln!(
+                    out,
+                    "{:<5} {:<9} {:>14.2} {:>14.8} {:>16.2}",
+                    quote.side,
+                    "total",
+                    quote.summary.average_price,
+                    quote.summary.quantity,
+                    quote.summary.notional
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Print a book, asks from the highest price down to the best ask and then bids from the best bid down.
+// In csv and table form a price quoted by several venues gets one row per venue.
+pub fn write_book(
+    out: &mut impl Write,
+    format: OutputFormat,
+    product: &Product,
+    book: &OrderBook,
+) -> Result<(), AggregatorError> {
+    let ladder = || {
+        let asks = book.asks.values().rev().map(|level| ("ask", level));
+        let bids = book.bids.values().rev().map(|level| ("bid", level));
+        asks.chain(bids).flat_map(|(side, level)| {
+            level
+                .venue_quantities()
+                .map(move |venue| (side, level.price.0, venue))
+        })
+    };
+    match format {
+        OutputFormat::Json => {
+            let json = BookJson {
+                product: product.to_string(),
+                book,
+            };
+            serde_json::to_writer_pretty(&mut *out, &json)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(out, "side,price,quantity,exchange")?;
+            for (side, price, venue) in ladder() {
+                writeln!(
+                    out,
+                    "{},{},{},{}",
+                    side, price, venue.quantity, venue.exchange
+                )?;
+            }
+        }
+        OutputFormat::Table => {
+            writeln!(
+                out,
+                "{:<5} {:>14} {:>14} {:<9}",
+                "SIDE", "PRICE", "QUANTITY", "EXCHANGE"
+            )?;
+            for (side, price, venue) in ladder() {
+                writeln!(
+                    out,
+                    "{:<5} {:>14.2} {:>14.8} {:<9}",
+                    side,
+                    price,
+                    venue.quantity,
+                    venue.exchange.to_string()
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Print the child orders of every backtest followed by its totals.
+// In csv and table form the totals row uses "total" as time and the arrival mid as mid.
+pub fn write_backtests(
+    out: &mut impl Write,
+    format: OutputFormat,
+    product: &Product,
+    results: &[BacktestResult],
+) -> Result<(), AggregatorError> {
+    match format {
+        OutputFormat::Json => {
+            let json = BacktestJson {
+                product: product.to_string(),
+                results,
+            };
+            serde_json::to_writer_pretty(&mut *out, &json)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(out, "side,time_ms,mid,quantity,average_price,notional")?;
+            for result in results {
+                for child in &result.children {
+                    writeln!(
+                        out,
+                        "{},{},{},{},{},{}",
+                        result.side,
+                        child.time_ms,
+                        child.mid,
+                        child.summary.quantity,
+                        child.summary.average_price,
+                        child.summary.notional
+                    )?;
+                }
+                writeln!(
+                    out,
+                    "{},total,{},{},{},{}",
+                    result.side,
+                    result.arrival_mid,
+                    result.summary.quantity,
+                    result.summary.average_price,
+                    result.summary.notional
+                )?;
+            }
+        }
+        OutputFormat::Table => {
+            for result in results {
+                writeln!(
+                    out,
+                    "{:<5} {:>14} {:>14} {:>14} {:>14} {:>16}",
+                    "SIDE", "TIME", "MID", "QUANTITY", "VWAP", "NOTIONAL"
+                )?;
+                for child in &result.children {
+                    writeln!(
+                        out,
+                        "{:<5} {:>14} {:>14.2} {:>14.8} {:>14.2} {:>16.2}",
+                        result.side,
+                        child.time_ms,
+                        child.mid,
+                        child.summary.quantity,
+                        child.summary.average_price,
+                        child.summary.notional
+                    )?;
+                }
+                writeln!(
+                    out,
+                    "{:<5} {:>14} {:>14.2} {:>14.8} {:>14.2} {:>16.2}",
+                    result.side,
+                    "total",
+                    result.arrival_mid,
+                    result.summary.quantity,
+                    result.summary.average_price,
+                    result.summary.notional
+                )?;
+                writeln!(
+                    out,
+                    "Slippage vs arrival mid: {:.2} bps",
+                    result.slippage_bps
+                )?;
+                for venue in &result.venues {
+                    writeln!(
+                        out,
+                        "  {:<9} {:>14.8} {:>6.1}%",
+                        venue.exchange,
+                        venue.quantity,
+                        venue.share * 100.0
+                    )?;
+                }
+                writeln!(out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Print the planned slices followed by the estimated totals of the remaining schedule.
+// In csv and table form the totals row uses "total" as slice and offset.
+pub fn write_plan(
+    out: &mut impl Write,
+    format: OutputFormat,
+    product: &Product,
+    plan: &Plan,
+) -> Result<(), AggregatorError> {
+    match format {
+        OutputFormat::Json => {
+            let json = PlanJson {
+                product: product.to_string(),
+                plan,
+            };
+            // One plan per line, so re-plans can be streamed
+            serde_json::to_writer(&mut *out, &json)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(
+                out,
+                "side,slice,offset_ms,quantity,average_price,notional,slippage_bps"
+            )?;
+            for slice in &plan.slices {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{}",
+                    plan.side,
+                    slice.index,
+                    slice.offset_ms,
+                    slice.quantity,
+                    slice.estimate.average_price,
+                    slice.estimate.notional,
+                    slice.slippage_bps
+                )?;
+            }
+            writeln!(
+                out,
+                "{},total,total,{},{},{},{}",
+                plan.side,
+                plan.remaining,
+                plan.estimate.average_price,
+                plan.estimate.notional,
+                plan.slippage_bps
+            )?;
+        }
+        OutputFormat::Table => {
+            writeln!(
+                out,
+                "{} {} executed {:.8}, remaining {:.8}, mid {:.2}",
+                product, plan.side, plan.executed, plan.remaining, plan.mid
+            )?;
+            writeln!(
+                out,
+                "{:<5} {:>10} {:>14} {:>14} {:>16} {:>10}",
+                "SLICE", "OFFSET", "QUANTITY", "EST PRICE", "EST NOTIONAL", "BPS"
+            )?;
+            for slice in &plan.slices {
+                writeln!(
+                    out,
+                    "{:<5} {:>8} s {:>14.8} {:>14.2} {:>16.2} {:>10.2}",
+                    slice.index,
+                    slice.offset_ms / 1000,
+                    slice.quantity,
+                    slice.estimate.average_price,
+                    slice.estimate.notional,
+                    slice.slippage_bps
+                )?;
+            }
+            writeln!(
+                out,
+                "{:<5} {:>10} {:>14.8} {:>14.2} {:>16.2} {:>10.2}",
+                "total",
+                "",
+                plan.remaining,
+                plan.estimate.average_price,
+                plan.estimate.notional,
+                plan.slippage_bps
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Quantity per venue, e.g. "coinbase=2;gemini=0.5"
+fn venue_quantities(venues: &[VenueFill]) -> String {
+    venues
+        .iter()
+        .map(|venue| format!("{}={}", venue.exchange, venue.quantity))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+// Print the buy curve then the sell curve, one row per size.
+// Sizes deeper than the book are flagged with complete=false ("partial" in the table).
+pub fn write_impact(
+    out: &mut impl Write,
+    format: OutputFormat,
+    product: &Product,
+    curve: &ImpactCurve,
+) -> Result<(), AggregatorError> {
+    let rows = || {
+        let buy = curve.buy.iter().map(|point| (Side::Buy, point));
+        let sell = curve.sell.iter().map(|point| (Side::Sell, point));
+        buy.chain(sell)
+    };
+    match format {
+        OutputFormat::Json => {
+            let json = ImpactJson {
+                product: product.to_string(),
+                curve,
+            };
+            serde_json::to_writer_pretty(&mut *out, &json)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(
+                out,
+                "side,size,quantity,average_price,worst_price,slippage_bps,complete,venues"
+            )?;
+            for (side, point) in rows() {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{},{}",
+                    side,
+                    point.size,
+                    point.summary.quantity,
+                    point.summary.average_price,
+                    point.worst_price,
+                    point.slippage_bps,
+                    point.complete,
+                    venue_quantities(&point.venues)
+                )?;
+            }
+        }
+        OutputFormat::Table => {
+            writeln!(out, "{} mid {:.2}", product, curve.mid)?;
+            writeln!(
+                out,
+                "{:<5} {:>10} {:>14} {:>14} {:>10} {:<8} VENUES",
+                "SIDE", "SIZE", "VWAP", "WORST", "BPS", ""
+            )?;
+            for (side, point) in rows() {
+                writeln!(
+                    out,
+                    "{:<5} {:>10} {:>14.2} {:>14.2} {:>10.2} {:<8} {}",
+                    side,
+                    point.size,
+                    point.summary.average_price,
+                    point.worst_price,
+                    point.slippage_bps,
+                    if point.complete { "" } else { "partial" },
+                    venue_quantities(&point.venues)
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Print a bucketed book in the same ladder order as write_book, with the quantity of every
+// exchange in the bucket
+pub fn write_buckets(
+    out: &mut impl Write,
+    format: OutputFormat,
+    product: &Product,
+    book: &BucketedBook,
+) -> Result<(), AggregatorError> {
+    let ladder = || {
+        let asks = book.asks.iter().rev().map(|bucket| ("ask", bucket));
+        let bids = book.bids.iter().map(|bucket| ("bid", bucket));
+        asks.chain(bids)
+    };
+    match format {
+        OutputFormat::Json => {
+            let json = BucketsJson {
+                product: product.to_string(),
+                book,
+            };
+            serde_json::to_writer_pretty(&mut *out, &json)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(out, "side,price,quantity,notional,levels,exchanges")?;
+            for (side, bucket) in ladder() {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    side,
+                    bucket.price,
+                    bucket.quantity,
+                    bucket.notional,
+                    bucket.levels,
+                    venue_quantities(&bucket.exchanges)
+                )?;
+            }
+        }
+        OutputFormat::Table => {
+            writeln!(
+                out,
+                "{:<5} {:>14} {:>14} {:>16} {:>6} EXCHANGES",
+                "SIDE", "PRICE", "QUANTITY", "NOTIONAL", "LEVELS"
+            )?;
+            for (side, bucket) in ladder() {
+                writeln!(
+                    out,
+                    "{:<5} {:>14.2} {:>14.8} {:>16.2} {:>6} {}",
+                    side,
+                    bucket.price,
+                    bucket.quantity,
+                    bucket.notional,
+                    bucket.levels,
+                    venue_quantities(&bucket.exchanges)
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Print the arbitrage opportunities, most profitable first
+pub fn write_arbitrage(
+    out: &mut impl Write,
+    format: OutputFormat,
+    product: &Product,
+    opportunities: &[Opportunity],
+) -> Result<(), AggregatorError> {
+    match format {
+        OutputFormat::Json => {
+            let json = ArbitrageJson {
+                product: product.to_string(),
+                opportunities,
+            };
+            serde_json::to_writer_pretty(&mut *out, &json)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(out, "{}", ARBITRAGE_CSV_HEADER)?;
+            for opportunity in opportunities {
+                write_arbitrage_csv_row(out, opportunity)?;
+            }
+        }
+        OutputFormat::Table => {
+            if opportunities.is_empty() {
+                writeln!(out, "No arbitrage opportunities")?;
+            }
+            for opportunity in opportunities {
+                write_arbitrage_line(out, product, opportunity)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Print one opportunity found while watching the feed: a JSON object per line, or a csv row
+// with the header before the `first` one
+pub fn write_arbitrage_update(
+    out: &mut impl Write,
+    format: OutputFormat,
+    product: &Product,
+    opportunity: &Opportunity,
+    first: bool,
+) -> Result<(), AggregatorError> {
+    match format {
+        OutputFormat::Json => {
+            let json = ArbitrageUpdateJson {
+                product: product.to_string(),
+                opportunity,
+            };
+            serde_json::to_writer(&mut *out, &json)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            if first {
+                writeln!(out, "{}", ARBITRAGE_CSV_HEADER)?;
+            }
+            write_arbitrage_csv_row(out, opportunity)?;
+        }
+        OutputFormat::Table => write_arbitrage_line(out, product, opportunity)?,
+    }
+    Ok(())
+}
+
+const ARBITRAGE_CSV_HEADER: &str =
+    "buy_exchange,sell_exchange,quantity,buy_vwap,sell_vwap,expected_profit";
+
+fn write_arbitrage_csv_row(
+    out: &mut impl Write,
+    opportunity: &Opportunity,
+) -> Result<(), AggregatorError> {
+    writeln!(
+        out,
+        "{},{},{},{},{},{}",
+        opportunity.buy_exchange,
+        opportunity.sell_exchange,
+        opportunity.quantity,
+        opportunity.buy_vwap,
+        opportunity.sell_vwap,
+        opportunity.expected_profit
+    )?;
+    Ok(())
+}
+
+fn write_arbitrage_line(
+    out: &mut impl Write,
+    product: &Product,
+    opportunity: &Opportunity,
+) -> Result<(), AggregatorError> {
+    writeln!(
+        out,
+        "Buy {} {} {} @ {:.2}, sell {} @ {:.2} : profit ${:.2}",
+        opportunity.buy_exchange,
+        opportunity.quantity,
+        product,
+        opportunity.buy_vwap,
+        opportunity.sell_exchange,
+        opportunity.sell_vwap,
+        opportunity.expected_profit
+    )?;
+    Ok(())
+}
+
+// Print the outcome of every venue of a round. The csv form carries the error kind only, the
+// table adds its message.
+pub fn write_venues(
+    out: &mut impl Write,
+    format: OutputFormat,
+    product: &Product,
+    venues: &[VenueReport],
+) -> Result<(), AggregatorError> {
+    match format {
+        OutputFormat::Json => {
+            let json = VenuesJson {
+                product: product.to_string(),
+                venues: venues
+                    .iter()
+                    .map(|venue| VenueJson {
+                        venue: venue.venue.clone(),
+                        status: venue.status.to_string(),
+                        latency_ms: venue.latency.as_millis(),
+                        bid_levels: venue.bid_levels,
+                        ask_levels: venue.ask_levels,
+                        error: venue.error.map(|kind| kind.to_string()),
+                        error_message: venue.error_message.clone(),
+                        http_status: venue.venue_error.as_ref().map(|error| error.status),
+                        retryable: venue.retryable,
+                    })
+                    .collect(),
+            };
+            serde_json::to_writer_pretty(&mut *out, &json)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(out, "venue,status,latency_ms,bid_levels,ask_levels,error")?;
+            for venue in venues {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    venue.venue,
+                    venue.status,
+                    venue.latency.as_millis(),
+                    venue.bid_levels,
+                    venue.ask_levels,
+                    venue.error.map(|kind| kind.to_string()).unwrap_or_default()
+                )?;
+            }
+        }
+        OutputFormat::Table => {
+            writeln!(
+                out,
+                "{:<10} {:<10} {:>8} {:>6} {:>6}  ERROR",
+                "VENUE", "STATUS", "LATENCY", "BIDS", "ASKS"
+            )?;
+            for venue in venues {
+                let error = match (venue.error, &venue.error_message) {
+                    (Some(kind), Some(message)) => format!("{}: {}", kind, message),
+                    (Some(kind), None) => kind.to_string(),
+                    (None, _) => String::new(),
+                };
+                writeln!(
+                    out,
+                    "{:<10} {:<10} {:>5} ms {:>6} {:>6}  {}",
+                    venue.venue,
+                    venue.status.to_string(),
+                    venue.latency.as_millis(),
+                    venue.bid_levels,
+                    venue.ask_levels,
+                    error
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Print one row for the consolidated book ("agg") and one per venue, with two columns per
+// depth band holding the bid and ask quantity within that distance of mid
+pub fn write_stats(
+    out: &mut impl Write,
+    format: OutputFormat,
+    product: &Product,
+    stats: &MarketStats,
+) -> Result<(), AggregatorError> {
+    let rows = || {
+        let venues = stats
+            .venues
+            .iter()
+            .map(|venue| (venue.venue.as_str(), &venue.stats));
+        std::iter::once(("agg", &stats.consolidated)).chain(venues)
+    };
+    let bands = &stats.consolidated.depth;
+    // How a row is laid out and how an optional figure is printed
+    type Line = fn(&[String]) -> String;
+    type Figure = fn(Option<f64>) -> String;
+    let (line, price): (Line, Figure) = match format {
+        OutputFormat::Json => {
+            let json = StatsJson {
+                product: product.to_string(),
+                stats,
+            };
+            serde_json::to_writer_pretty(&mut *out, &json)?;
+            writeln!(out)?;
+            return Ok(());
+        }
+        OutputFormat::Csv => (
+            |cells| cells.join(","),
+            |value| value.map(|value| value.to_string()).unwrap_or_default(),
+        ),
+        OutputFormat::Table => (
+            |cells| {
+                let (venue, figures) = cells.split_first().expect("venue column");
+                let figures: Vec<String> = figures
+                    .iter()
+                    .map(|figure| format!("{:>14}", figure))
+                    .collect();
+                format!("{:<10} {}", venue, figures.join(" "))
+            },
+            |value| value.map_or_else(|| "-".to_string(), |value| format!("{:.2}", value)),
+        ),
+    };
+    let mut header = vec![
+        "venue".to_string(),
+        "best_bid".to_string(),
+        "best_ask".to_string(),
+        "mid".to_string(),
+        "spread_bps".to_string(),
+        "microprice".to_string(),
+        "depth_imbalance".to_string(),
+        "order_flow_imbalance".to_string(),
+    ];
+    for band in bands {
+        header.push(format!("bid_qty_{}pct", band.pct));
+        header.push(format!("ask_qty_{}pct", band.pct));
+    }
+    writeln!(out, "{}", line(&header))?;
+    for (venue, book_stats) in rows() {
+        let BookStats {
+            best_bid,
+            best_ask,
+            mid,
+            spread_bps,
+            microprice,
+            depth_imbalance,
+            order_flow_imbalance,
+            depth,
+            ..
+        } = book_stats;
+        let mut row = vec![
+            venue.to_string(),
+            price(*best_bid),
+            price(*best_ask),
+            price(*mid),
+            price(*spread_bps),
+            price(*microprice),
+            price(*depth_imbalance),
+            price(*order_flow_imbalance),
+        ];
+        for band in depth {
+            row.push(band.bid_quantity.to_string());
+            row.push(band.ask_quantity.to_string());
+        }
+        writeln!(out, "{}", line(&row))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aggregator::VenueStatus, error::ErrorKind, types::Exchange};
+    use std::time::Duration;
+
+    fn book() -> OrderBook {
+        let mut book = OrderBook::new(Exchange::AggregatedExchange);
+        book.add_bid(99.0, 1.0);
+        book.add_ask(101.0, 1.0);
+        book.add_ask(102.0, 2.0);
+        book
+    }
+
+    fn render(write: impl FnOnce(&mut Vec<u8>) -> Result<(), AggregatorError>) -> String {
+        let mut out = Vec::new();
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_quote_csv() {
+        let fills = book().calculate_best_buy_offer(2.0).unwrap();
+        let quotes = [Quote::new(Side::Buy, fills)];
+        let csv = render(|out| write_quotes(out, OutputFormat::Csv, &Product::BTCUSD, &quotes));
+        assert_eq!(
+            csv,
+            "side,exchange,price,quantity,notional\n\
+             buy,agg,101,1,101\n\
+             buy,agg,102,1,102\n\
+             buy,total,101.5,2,203\n"
+        );
+    }
+
+    #[test]
+    fn test_quote_json() {
+        let fills = book().calculate_best_sell_offer(1.0).unwrap();
+        let quotes = [Quote::new(Side::Sell, fills)];
+        let json = render(|out| write_quotes(out, OutputFormat::Json, &Product::BTCUSD, &quotes));
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["product"], "BTC-USD");
+        assert_eq!(value["quotes"][0]["side"], "sell");
+        assert_eq!(value["quotes"][0]["notional"], "99");
+        assert_eq!(value["quotes"][0]["fills"][0]["price"], "99");
+    }
+
+    #[test]
+    fn test_impact_csv() {
+        let curve = book().impact_curve(&[1.0]).unwrap();
+        let csv = render(|out| write_impact(out, OutputFormat::Csv, &Product::BTCUSD, &curve));
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[1].starts_with("buy,1,1,"));
+        assert!(rows[1].ends_with(",true,agg=1"));
+    }
+
+    #[test]
+    fn test_arbitrage_formats() {
+        let opportunities = [Opportunity {
+            buy_exchange: Exchange::Coinbase,
+            sell_exchange: Exchange::Gemini,
+            quantity: 0.5,
+            buy_vwap: 100.0,
+            sell_vwap: 101.0,
+            expected_profit: 0.5,
+        }];
+        let write =
+            |format| render(|out| write_arbitrage(out, format, &Product::BTCUSD, &opportunities));
+        assert_eq!(
+            write(OutputFormat::Csv),
+            "buy_exchange,sell_exchange,quantity,buy_vwap,sell_vwap,expected_profit\n\
+             coinbase,gemini,0.5,100,101,0.5\n"
+        );
+        let value: serde_json::Value = serde_json::from_str(&write(OutputFormat::Json)).unwrap();
+        assert_eq!(value["product"], "BTC-USD");
+        assert_eq!(value["opportunities"][0]["sell_vwap"], "101");
+        let empty = render(|out| write_arbitrage(out, OutputFormat::Table, &Product::BTCUSD, &[]));
+        assert_eq!(empty, "No arbitrage opportunities\n");
+
+        let update = |format, first| {
+            render(|out| {
+                write_arbitrage_update(out, format, &Product::BTCUSD, &opportunities[0], first)
+            })
+        };
+        assert_eq!(
+            update(OutputFormat::Csv, false),
+            "coinbase,gemini,0.5,100,101,0.5\n"
+        );
+        assert!(update(OutputFormat::Csv, true).starts_with("buy_exchange,"));
+        let json = update(OutputFormat::Json, true);
+        assert_eq!(json.lines().count(), 1);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["product"], "BTC-USD");
+        assert_eq!(value["buy_exchange"], "coinbase");
+    }
+
+    #[test]
+    fn test_book_csv_ladder() {
+        let csv = render(|out| write_book(out, OutputFormat::Csv, &Product::BTCUSD, &book()));
+        assert_eq!(
+            csv,
+            "side,price,quantity,exchange\n\
+             ask,102,2,agg\n\
+             ask,101,1,agg\n\
+             bid,99,1,agg\n"
+        );
+    }
+
+    #[test]
+    fn test_book_csv_splits_shared_prices() {
+        let mut book = OrderBook::new(Exchange::Coinbase);
+        book.add_ask(101.0, 1.0);
+        let mut gemini = OrderBook::new(Exchange::Gemini);
+        gemini.add_ask(101.0, 2.0);
+        book.merge(&gemini);
+        let csv = render(|out| write_book(out, OutputFormat::Csv, &Product::BTCUSD, &book));
+        assert_eq!(
+            csv,
+            "side,price,quantity,exchange\n\
+             ask,101,1,coinbase\n\
+             ask,101,2,gemini\n"
+        );
+    }
+
+    #[test]
+    fn test_venues_formats() {
+        let ok = VenueReport {
+            venue: "Coinbase".to_string(),
+            status: VenueStatus::Success,
+            latency: Duration::from_millis(42),
+            bid_levels: 50,
+            ask_levels: 49,
+            error: None,
+            error_message: None,
+            venue_error: None,
+            retryable: false,
+        };
+        let failed = VenueReport {
+            venue: "Gemini".to_string(),
+            status: VenueStatus::TimedOut,
+            bid_levels: 0,
+            ask_levels: 0,
+            error: Some(ErrorKind::Timeout),
+            error_message: Some("No response before the aggregation deadline".to_string()),
+            retryable: true,
+            ..ok.clone()
+        };
+        let venues = [ok, failed];
+        let write = |format| render(|out| write_venues(out, format, &Product::BTCUSD, &venues));
+        assert_eq!(
+            write(OutputFormat::Csv),
+            "venue,status,latency_ms,bid_levels,ask_levels,error\n\
+             Coinbase,success,42,50,49,\n\
+             Gemini,timed_out,42,0,0,timeout\n"
+        );
+        let value: serde_json::Value = serde_json::from_str(&write(OutputFormat::Json)).unwrap();
+        assert_eq!(value["product"], "BTC-USD");
+        assert_eq!(value["venues"][1]["error"], "timeout");
+        assert_eq!(value["venues"][1]["retryable"], true);
+        assert!(write(OutputFormat::Table).contains("timeout: No response before"));
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{
+    aggregator::OrderBookAggregator,
+    error::AggregatorError,
+    order_book::{FillSummary, OrderBook},
+    types::Side,
+};
+use serde::Serialize;
+use std::time::Duration;
+
+// Most child orders a schedule may be split into, every plan lists each remaining slice
+pub const MAX_SLICES: usize = 10_000;
+
+// How the parent quantity is spread over the slices of a schedule
+#[derive(Debug, Clone, PartialEq)]
+pub enum SliceProfile {
+    // Equal slices, a TWAP schedule
+    Uniform,
+    // Slices proportional to the expected volume of their interval, a VWAP schedule.
+    // Weights are relative, one per slice, the last one repeats if there are fewer.
+    Volume(Vec<f64>),
+}
+
+// Child order proposed for one interval of the schedule
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedSlice {
+    pub index: usize,
+    // Offset of the interval from the start of the schedule
+    pub offset_ms: u64,
+    #[serde(with = "crate::decimal_string")]
+    pub quantity: f64,
+    // Cost of sweeping the slice against the book the plan was made from
+    pub estimate: FillSummary,
+    pub slippage_bps: f64,
+}
+
+// Remaining schedule as planned from one book
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    pub side: Side,
+    #[serde(with = "crate::decimal_string")]
+    pub executed: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub remaining: f64,
+    #[serde(with = "crate::decimal_string")]
+    pub mid: f64,
+    pub slices: Vec<PlannedSlice>,
+    // Totals of every remaining slice
+    pub estimate: FillSummary,
+    pub slippage_bps: f64,
+}
+
+// Splits a parent order into child slices over a horizon and keeps track of what was executed.
+// Every plan spreads the quantity still to execute over the slices still to come and prices
+// each slice against the current book. Slices are priced independently, assuming the book
+// refills between intervals.
+#[derive(Debug, Clone)]
+pub struct SchedulePlanner {
+    side: Side,
+    quantity: f64,
+    horizon: Duration,
+    slices: usize,
+    profile: SliceProfile,
+    executed: f64,
+    next_slice: usize,
+}
+
+impl SchedulePlanner {
+    // Uniform (TWAP) schedule of `slices` child orders over `horizon`. The quantity must be
+    // positive and the horizon long enough to give every slice a non-zero interval.
+    pub fn new(
+        side: Side,
+        quantity: f64,
+        horizon: Duration,
+        slices: usize,
+    ) -> Result<Self, AggregatorError> {
+        if !(quantity.is_finite() && quantity > 0.0) {
+            return Err(AggregatorError::InvalidSchedule(format!(
+                "quantity must be positive, got {}",
+                quantity
+            )));
+        }
+        if !(1..=MAX_SLICES).contains(&slices) {
+            return Err(AggregatorError::InvalidSchedule(format!(
+                "slices must be between 1 and {}, got {}",
+                MAX_SLICES, slices
+            )));
+        }
+        if (horizon / slices as u32).is_zero() {
+            return Err(AggregatorError::InvalidSchedule(format!(
+                "a horizon of {:?} is too short for {} slices",
+                horizon, slices
+            )));
+        }
+        Ok(SchedulePlanner {
+            side,
+            quantity,
+            horizon,
+            slices,
+            profile: SliceProfile::Uniform,
+            executed: 0.0,
+            next_slice: 0,
+        })
+    }
+
+    pub fn with_profile(mut self, profile: SliceProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    // Time between the starts of two slices, never zero
+    pub fn interval(&self) -> Duration {
+        self.horizon / self.slices as u32
+    }
+
+    pub fn remaining(&self) -> f64 {
+        (self.quantity - self.executed).max(0.0)
+    }
+
+    // True once every slice was executed or the whole quantity filled
+    pub fn is_done(&self) -> bool {
+        self.next_slice >= self.slices || self.remaining() <= 0.0
+    }
+
+    // Record the quantity filled by the current slice and move on to the next one.
+    // A short fill is carried over to the following slices by the next plan.
+    pub fn record_fill(&mut self, quantity: f64) {
+        self.executed += quantity;
+        self.next_slice += 1;
+    }
+
+    fn weight(&self, index: usize) -> f64 {
+        match &self.profile {
+            SliceProfile::Uniform => 1.0,
+            SliceProfile::Volume(weights) => weights
+                .get(index)
+                .or(weights.last())
+                .copied()
+                .unwrap_or(1.0)
+                .max(0.0),
+        }
+    }
+
+    // Plan the remaining slices against `book`
+    pub fn plan(&self, book: &OrderBook) -> Result<Plan, AggregatorError> {
+        let mid = book.mid_price().ok_or_else(|| {
+            AggregatorError::InsufficientLiquidity("No two sided book to plan from".to_string())
+        })?;
+        let remaining = self.remaining();
+        let indices = self.next_slice..self.slices;
+        let total_weight: f64 = indices.clone().map(|index| self.weight(index)).sum();
+        let interval_ms = self.interval().as_millis() as u64;
+
+        let mut fills = Vec::new();
+        let mut slices = Vec::new();
+        for index in indices {
+            let quantity = if total_weight > 0.0 {
+                remaining * self.weight(index) / total_weight
+            } else {
+                remaining / (self.slices - self.next_slice) as f64
+            };
+            let slice_fills = book.calculate_best_offer(self.side, quantity)?;
+            let estimate = FillSummary::from_fills(&slice_fills);
+            slices.push(PlannedSlice {
+                index,
+                offset_ms: index as u64 * interval_ms,
+                quantity,
+                estimate,
+                slippage_bps: estimate.slippage_bps(self.side, mid),
+            });
+            fills.extend(slice_fills);
+        }
+
+        let estimate = FillSummary::from_fills(&fills);
+        Ok(Plan {
+            side: self.side,
+            executed: self.executed,
+            remaining,
+            mid,
+            slices,
+            estimate,
+            slippage_bps: estimate.slippage_bps(self.side, mid),
+        })
+    }
+
+    // Plan the remaining slices against a fresh consolidated book
+    pub async fn replan(&self, aggregator: &OrderBookAggregator) -> Result<Plan, AggregatorError> {
+        let report = aggregator.fetch_and_aggregate_data().await?;
+        self.plan(&report.book)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Exchange;
+
+    fn book() -> OrderBook {
+        let mut book = OrderBook::new(Exchange::Coinbase);
+        book.add_bid(99.0, 10.0);
+        book.add_ask(101.0, 1.0);
+        book.add_ask(103.0, 10.0);
+        book
+    }
+
+    #[test]
+    fn test_twap_plan() {
+        let planner = SchedulePlanner::new(Side::Buy, 4.0, Duration::from_secs(60), 4).unwrap();
+        let plan = planner.plan(&book()).unwrap();
+        assert_eq!(plan.mid, 100.0);
+        assert_eq!(plan.slices.len(), 4);
+        assert_eq!(plan.slices[3].offset_ms, 45_000);
+        // Every 1 BTC slice fits the best ask
+        assert!(
+            plan.slices
+                .iter()
+                .all(|slice| slice.estimate.average_price == 101.0)
+        );
+        assert_eq!(plan.slippage_bps, 100.0);
+    }
+
+    #[test]
+    fn test_replan_carries_short_fill() {
+        let mut planner = SchedulePlanner::new(Side::Sell, 6.0, Duration::from_secs(30), 3)
+            .unwrap()
+            .with_profile(SliceProfile::Volume(vec![1.0, 2.0]));
+        let plan = planner.plan(&book()).unwrap();
+        let quantities: Vec<f64> = plan.slices.iter().map(|slice| slice.quantity).collect();
+        assert_eq!(quantities, vec![1.2, 2.4, 2.4]);
+
+        // Only half of the first slice filled, the rest moves to the last two slices
+        planner.record_fill(0.6);
+        let plan = planner.plan(&book()).unwrap();
+        assert_eq!(plan.remaining, 5.4);
+        assert_eq!(plan.slices[0].index, 1);
+        assert_eq!(plan.slices[0].quantity, 2.7);
+
+        planner.record_fill(2.7);
+        planner.record_fill(2.7);
+        assert!(planner.is_done());
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        let new = |quantity, horizon, slices| {
+            SchedulePlanner::new(Side::Buy, quantity, horizon, slices).map(|_| ())
+        };
+        let minute = Duration::from_secs(60);
+        assert!(matches!(
+            new(1.0, Duration::ZERO, 4),
+            Err(AggregatorError::InvalidSchedule(_))
+        ));
+        assert!(new(1.0, minute, 0).is_err());
+        assert!(new(1.0, minute, MAX_SLICES + 1).is_err());
+        assert!(new(1.0, Duration::from_secs(86_400), MAX_SLICES).is_ok());
+        assert!(new(0.0, minute, 4).is_err());
+        assert!(new(f64::NAN, minute, 4).is_err());
+        // One nanosecond per slice is the shortest interval
+        assert!(new(1.0, Duration::from_nanos(3), 4).is_err());
+        assert!(new(1.0, Duration::from_nanos(4), 4).is_ok());
+    }
+}
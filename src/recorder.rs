@@ -0,0 +1,233 @@
+use crate::{
+    data_providers::DataProvider,
+    error::AggregatorError,
+    order_book::OrderBook,
+    types::{Product, unix_time_ms},
+};
+use async_trait::async_trait;
+use flate2::{Compression, read::MultiGzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Lines, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+// File format: a sequence of gzip members, each holding one Record as a JSON line.
+// Every record is compressed and appended on its own, so a crash can at most cut the last
+// record short and files from several runs can simply be concatenated.
+
+// One venue fetch as it was seen by the aggregator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub venue: String,
+    pub product: String,
+    // Unix milliseconds at which the fetch completed
+    pub recorded_at_ms: u64,
+    pub latency_ms: u64,
+    // Fetched book, None if the fetch failed
+    pub book: Option<OrderBook>,
+    // Error message of a failed fetch
+    pub error: Option<String>,
+}
+
+// Appends records to a compressed file, safe to share between providers. `append` blocks
+// on compression and file IO, async callers run it on the blocking pool.
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    // Open `path` for appending, creating it if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AggregatorError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Recorder {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn append(&self, record: &Record) -> Result<(), AggregatorError> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut encoder, record)?;
+        encoder.write_all(b"\n")?;
+        let member = encoder.finish()?;
+        // A single write keeps members of concurrent fetches from interleaving
+        let mut file = self
+            .file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        file.write_all(&member)?;
+        Ok(())
+    }
+}
+
+// Iterates over the records of a file written by Recorder, oldest first
+pub struct RecordReader {
+    lines: Lines<BufReader<MultiGzDecoder<BufReader<File>>>>,
+    // Set after a read error, the rest of a damaged file is not readable
+    failed: bool,
+}
+
+impl RecordReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, AggregatorError> {
+        let file = File::open(path)?;
+        let decoder = MultiGzDecoder::new(BufReader::new(file));
+        Ok(RecordReader {
+            lines: BufReader::new(decoder).lines(),
+            failed: false,
+        })
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = Result<Record, AggregatorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(error) => {
+                self.failed = true;
+                return Some(Err(error.into()));
+            }
+        };
+        Some(serde_json::from_str(&line).map_err(Into::into))
+    }
+}
+
+// DataProvider wrapper recording every fetch of the wrapped provider
+pub struct RecordingProvider {
+    inner: Arc<dyn DataProvider>,
+    recorder: Arc<Recorder>,
+}
+
+impl RecordingProvider {
+    pub fn new(inner: Arc<dyn DataProvider>, recorder: Arc<Recorder>) -> Self {
+        RecordingProvider { inner, recorder }
+    }
+}
+
+#[async_trait]
+impl DataProvider for RecordingProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.inner.now_ms()
+    }
+
+    async fn fetch_order_book(&self, product_id: Product) -> Result<OrderBook, AggregatorError> {
+        let product = product_id.to_string();
+        let started = Instant::now();
+        let result = self.inner.fetch_order_book(product_id).await;
+        let record = Record {
+            venue: self.inner.name().to_string(),
+            product,
+            recorded_at_ms: unix_time_ms(),
+            latency_ms: started.elapsed().as_millis() as u64,
+            book: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().map(ToString::to_string),
+        };
+        // Compressing and writing block, so they run on the blocking pool instead of holding
+        // up a runtime worker. Losing a record must not lose the quote, so write errors are
+        // only reported.
+        let recorder = Arc::clone(&self.recorder);
+        let written = match tokio::task::spawn_blocking(move || recorder.append(&record)).await {
+            Ok(written) => written,
+            Err(join_error) => Err(std::io::Error::from(join_error).into()),
+        };
+        if let Err(error) = written {
+            tracing::warn!(venue = %self.inner.name(), %error, "failed to record fetch");
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data_providers::mock::MockProvider, types::Exchange};
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.gz", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn record(price: f64) -> Record {
+        let mut book = OrderBook::new(Exchange::Coinbase);
+        book.add_bid(price, 1.0);
+        Record {
+            venue: "Coinbase".to_string(),
+            product: "BTC-USD".to_string(),
+            recorded_at_ms: 1,
+            latency_ms: 2,
+            book: Some(book),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_append_across_sessions() {
+        let path = temp_path("recorder-sessions");
+        Recorder::open(&path)
+            .unwrap()
+            .append(&record(99.0))
+            .unwrap();
+        // Reopening appends instead of truncating
+        Recorder::open(&path)
+            .unwrap()
+            .append(&record(98.0))
+            .unwrap();
+
+        let records: Vec<Record> = RecordReader::open(&path)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        let best_bid = |record: &Record| record.book.as_ref().unwrap().best_bid().unwrap().price.0;
+        assert_eq!(best_bid(&records[0]), 99.0);
+        assert_eq!(best_bid(&records[1]), 98.0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_tail_keeps_earlier_records() {
+        let path = temp_path("recorder-truncated");
+        let recorder = Recorder::open(&path).unwrap();
+        recorder.append(&record(99.0)).unwrap();
+        let complete = std::fs::metadata(&path).unwrap().len();
+        recorder.append(&record(98.0)).unwrap();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(complete + 10).unwrap();
+
+        let mut reader = RecordReader::open(&path).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recording_provider_keeps_errors() {
+        let path = temp_path("recorder-provider");
+        let recorder = Arc::new(Recorder::open(&path).unwrap());
+        let gemini = MockProvider::new("Gemini", OrderBook::new(Exchange::Gemini))
+            .with_error(AggregatorError::ExchangeError("maintenance".to_string()));
+        let provider = RecordingProvider::new(Arc::new(gemini), recorder);
+        assert!(provider.fetch_order_book(Product::BTCUSD).await.is_err());
+
+        let record = RecordReader::open(&path).unwrap().next().unwrap().unwrap();
+        assert_eq!(record.venue, "Gemini");
+        assert_eq!(record.product, "BTC-USD");
+        assert!(record.book.is_none());
+        assert_eq!(record.error.as_deref(), Some("maintenance"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{
+    aggregator::AggregationReport,
+    error::{AggregatorError, ErrorKind},
+    feed::BookFeed,
+    order_book::{DEFAULT_IMPACT_SIZES, FillSummary, ImpactPoint, Level, OrderBook},
+    server::AppState,
+    types::Side,
+};
+use std::sync::Arc;
+use tokio::{net::TcpListener, sync::mpsc};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status, transport::Server};
+
+pub mod proto {
+    tonic::include_proto!("orderbook.v1");
+}
+
+use proto::order_book_service_server::{OrderBookService, OrderBookServiceServer};
+
+// Levels per side returned when a request leaves depth at 0
+pub const DEFAULT_GRPC_DEPTH: usize = 10;
+// Books buffered per StreamBook client before the stream waits on it
+const STREAM_BUFFER: usize = 16;
+
+impl From<AggregatorError> for Status {
+    fn from(error: AggregatorError) -> Self {
+        let message = error.to_string();
+        match error.kind() {
+            ErrorKind::UnknownProduct => Status::not_found(message),
+            ErrorKind::InsufficientLiquidity => Status::failed_precondition(message),
+            ErrorKind::AggregationFailed | ErrorKind::PolicyNotMet => Status::unavailable(message),
+            _ => Status::internal(message),
+        }
+    }
+}
+
+impl From<&Level> for proto::Level {
+    fn from(level: &Level) -> Self {
+        proto::Level {
+            price: level.price.0,
+            quantity: level.quantity.0,
+            exchange: level.exchange.to_string(),
+            venues: level
+                .venue_quantities()
+                .map(|venue| proto::VenueQuantity {
+                    exchange: venue.exchange.to_string(),
+                    quantity: venue.quantity,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<ImpactPoint> for proto::ImpactPoint {
+    fn from(point: ImpactPoint) -> Self {
+        proto::ImpactPoint {
+            size: point.size,
+            quantity: point.summary.quantity,
+            notional: point.summary.notional,
+            average_price: point.summary.average_price,
+            worst_price: point.worst_price,
+            slippage_bps: point.slippage_bps,
+            complete: point.complete,
+            venues: point
+                .venues
+                .into_iter()
+                .map(|venue| proto::VenueFill {
+                    exchange: venue.exchange.to_string(),
+                    quantity: venue.quantity,
+                    notional: venue.notional,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn to_proto_book(product: &str, book: &OrderBook, depth: u32) -> proto::Book {
+    let depth = match depth {
+        0 => DEFAULT_GRPC_DEPTH,
+        depth => depth as usize,
+    };
+    proto::Book {
+        product: product.to_string(),
+        received_at_ms: book.received_at_ms,
+        bids: book
+            .bids
+            .values()
+            .rev()
+            .take(depth)
+            .map(Into::into)
+            .collect(),
+        asks: book.asks.values().take(depth).map(Into::into).collect(),
+    }
+}
+
+// OrderBookService backed by the live feeds of the HTTP server
+pub struct OrderBookGrpc {
+    state: Arc<AppState>,
+}
+
+impl OrderBookGrpc {
+    pub fn new(state: Arc<AppState>) -> Self {
+        OrderBookGrpc { state }
+    }
+
+    fn feed(&self, product: &str) -> Result<&BookFeed, Status> {
+        Ok(self.state.feed(product)?)
+    }
+}
+
+// The shared report of the latest round; handlers borrow its book rather than cloning it
+fn latest_report(feed: &BookFeed) -> Result<Arc<AggregationReport>, Status> {
+    feed.latest().ok_or_else(|| {
+        Status::unavailable(format!(
+            "No order book available yet for {}",
+            feed.product()
+        ))
+    })
+}
+
+#[tonic::async_trait]
+impl OrderBookService for OrderBookGrpc {
+    async fn get_book(
+        &self,
+        request: Request<proto::GetBookRequest>,
+    ) -> Result<Response<proto::Book>, Status> {
+        let request = request.into_inner();
+        let feed = self.feed(&request.product)?;
+        let report = latest_report(feed)?;
+        Ok(Response::new(to_proto_book(
+            &feed.product().to_string(),
+            &report.book,
+            request.depth,
+        )))
+    }
+
+    async fn get_quote(
+        &self,
+        request: Request<proto::GetQuoteRequest>,
+    ) -> Result<Response<proto::Quote>, Status> {
+        let request = request.into_inner();
+        let side = match request.side() {
+            proto::Side::Buy => Side::Buy,
+            proto::Side::Sell => Side::Sell,
+            proto::Side::Unspecified => {
+                return Err(Status::invalid_argument("side must be BUY or SELL"));
+            }
+        };
+        if request.quantity.is_nan() || request.quantity <= 0.0 {
+            return Err(Status::invalid_argument("quantity must be positive"));
+        }
+        let feed = self.feed(&request.product)?;
+        let report = latest_report(feed)?;
+        let fills = report.book.calculate_best_offer(side, request.quantity)?;
+        let summary = FillSummary::from_fills(&fills);
+        Ok(Response::new(proto::Quote {
+            product: feed.product().to_string(),
+            side: request.side,
+            quantity: summary.quantity,
+            notional: summary.notional,
+            average_price: summary.average_price,
+            fills: fills
+                .into_iter()
+                .map(|fill| proto::Fill {
+                    price: fill.price,
+                    quantity: fill.quantity,
+                    exchange: fill.exchange,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_impact_curve(
+        &self,
+        request: Request<proto::GetImpactCurveRequest>,
+    ) -> Result<Response<proto::ImpactCurve>, Status> {
+        let request = request.into_inner();
+        if request
+            .sizes
+            .iter()
+            .any(|size| size.is_nan() || *size <= 0.0)
+        {
+            return Err(Status::invalid_argument("sizes must be positive"));
+        }
+        let sizes = if request.sizes.is_empty() {
+            DEFAULT_IMPACT_SIZES.to_vec()
+        } else {
+            request.sizes
+        };
+        let feed = self.feed(&request.product)?;
+        let curve = latest_report(feed)?.book.impact_curve(&sizes)?;
+        Ok(Response::new(proto::ImpactCurve {
+            product: feed.product().to_string(),
+            mid: curve.mid,
+            buy: curve.buy.into_iter().map(Into::into).collect(),
+            sell: curve.sell.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    type StreamBookStream = ReceiverStream<Result<proto::Book, Status>>;
+
+    async fn stream_book(
+        &self,
+        request: Request<proto::StreamBookRequest>,
+    ) -> Result<Response<Self::StreamBookStream>, Status> {
+        let request = request.into_inner();
+        let feed = self.feed(&request.product)?;
+        let mut updates = feed.subscribe();
+        let product = feed.product().to_string();
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            // The watch channel only keeps the newest round, so a slow stream skips versions
+            updates.mark_changed();
+            let mut sent: Option<Arc<AggregationReport>> = None;
+            while updates.changed().await.is_ok() {
+                let Some(report) = updates.borrow_and_update().report.clone() else {
+                    continue;
+                };
+                // Failed rounds also notify, but leave the last good report in place
+                if sent.as_ref().is_some_and(|sent| Arc::ptr_eq(sent, &report)) {
+                    continue;
+                }
+                let book = to_proto_book(&product, &report.book, request.depth);
+                if sender.send(Ok(book)).await.is_err() {
+                    break;
+                }
+                sent = Some(report);
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+// Serve the gRPC API on `listener` until the process exits
+pub async fn serve(listener: TcpListener, state: Arc<AppState>) -> Result<(), AggregatorError> {
+    Server::builder()
+        .add_service(OrderBookServiceServer::new(OrderBookGrpc::new(state)))
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+        .map_err(|error| AggregatorError::Io(std::io::Error::other(error)))
+}
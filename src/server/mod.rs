@@ -0,0 +1,141 @@
+use crate::{
+    error::{AggregatorError, ErrorKind},
+    feed::BookFeed,
+    metrics::{self, Metrics},
+    types::Product,
+};
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::net::TcpListener;
+pub mod grpc;
+pub mod rest;
+pub mod ws;
+
+// Age of the last successful round from which the book of a feed is reported as stale
+pub const DEFAULT_MAX_FEED_AGE: Duration = Duration::from_secs(30);
+
+// Shared state of the HTTP server, one live feed per product
+pub struct AppState {
+    feeds: HashMap<Product, BookFeed>,
+    // Registry served on /metrics, shared with the aggregators of the feeds
+    metrics: Option<Arc<Metrics>>,
+    max_feed_age: Duration,
+}
+
+impl AppState {
+    pub fn new(feeds: Vec<BookFeed>) -> Self {
+        AppState {
+            feeds: feeds
+                .into_iter()
+                .map(|feed| (feed.product().clone(), feed))
+                .collect(),
+            metrics: None,
+            max_feed_age: DEFAULT_MAX_FEED_AGE,
+        }
+    }
+
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn with_max_feed_age(mut self, max_feed_age: Duration) -> Self {
+        self.max_feed_age = max_feed_age;
+        self
+    }
+
+    pub fn max_feed_age(&self) -> Duration {
+        self.max_feed_age
+    }
+
+    // Whether a book of `age_ms`, None before the first successful round, is too old to trust
+    pub fn is_stale(&self, age_ms: Option<u64>) -> bool {
+        age_ms.is_none_or(|age_ms| age_ms >= self.max_feed_age.as_millis() as u64)
+    }
+
+    // Feed for the product named in a request path
+    pub fn feed(&self, product: &str) -> Result<&BookFeed, AggregatorError> {
+        let product: Product = product.parse()?;
+        self.feeds
+            .get(&product)
+            .ok_or_else(|| AggregatorError::UnknownProduct(product.to_string()))
+    }
+
+    pub fn feeds(&self) -> impl Iterator<Item = &BookFeed> {
+        self.feeds.values()
+    }
+}
+
+// Error returned by a handler, rendered as `{"error": ...}`
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<AggregatorError> for ApiError {
+    fn from(error: AggregatorError) -> Self {
+        let status = match error.kind() {
+            ErrorKind::UnknownProduct => StatusCode::NOT_FOUND,
+            ErrorKind::InsufficientLiquidity => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorKind::AggregationFailed | ErrorKind::PolicyNotMet => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(status, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+// GET /metrics
+async fn get_metrics(State(state): State<Arc<AppState>>) -> Result<Response, ApiError> {
+    let metrics = state
+        .metrics
+        .as_ref()
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Metrics are not enabled"))?;
+    Ok((
+        [(header::CONTENT_TYPE, metrics::CONTENT_TYPE)],
+        metrics.encode(),
+    )
+        .into_response())
+}
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/book/{product}", get(rest::get_book))
+        .route("/quote/{product}", get(rest::get_quote))
+        .route("/impact/{product}", get(rest::get_impact))
+        .route("/stats/{product}", get(rest::get_stats))
+        .route("/venues/health", get(rest::get_venues_health))
+        .route("/metrics", get(get_metrics))
+        .route("/ws", get(ws::ws_handler))
+        .with_state(state)
+}
+
+// Serve the API on `listener` until the process exits
+pub async fn serve(listener: TcpListener, state: Arc<AppState>) -> Result<(), AggregatorError> {
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
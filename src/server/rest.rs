@@ -0,0 +1,542 @@
+use crate::{
+    aggregator::AggregationReport,
+    analytics::{AnalyticsConfig, MarketStats},
+    feed::BookFeed,
+    order_book::{
+        BucketWidth, BucketedBook, DEFAULT_IMPACT_SIZES, FillSummary, ImpactCurve, OrderBook,
+        OrderDetails,
+    },
+    server::{ApiError, AppState},
+    types::{Side, unix_time_ms},
+};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+// Levels per side returned by /book when no depth is given
+pub const DEFAULT_BOOK_DEPTH: usize = 10;
+
+#[derive(Debug, Deserialize)]
+pub struct BookQuery {
+    depth: Option<usize>,
+    // Price bucket width, e.g. 10 or 1bp
+    bucket: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum BookView {
+    Levels(OrderBook),
+    Buckets(BucketedBook),
+}
+
+// Consolidated book limited to the requested depth, in the OrderBook JSON schema, or its
+// price buckets when a bucket width is given
+#[derive(Debug, Serialize)]
+pub struct BookResponse {
+    product: String,
+    #[serde(flatten)]
+    freshness: Freshness,
+    #[serde(flatten)]
+    book: BookView,
+}
+
+// How long ago the served book was aggregated, and whether that is past the maximum feed age.
+// The last good book keeps being served while rounds fail.
+#[derive(Debug, Serialize)]
+pub struct Freshness {
+    age_ms: u64,
+    stale: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuoteQuery {
+    side: Side,
+    qty: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuoteResponse {
+    product: String,
+    #[serde(flatten)]
+    freshness: Freshness,
+    side: Side,
+    #[serde(flatten)]
+    summary: FillSummary,
+    fills: Vec<OrderDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImpactQuery {
+    // Comma separated sizes, e.g. 1,5,10,50
+    sizes: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImpactResponse {
+    product: String,
+    #[serde(flatten)]
+    curve: ImpactCurve,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    // Levels per side looked at by the depth and order flow imbalances
+    levels: Option<usize>,
+    // Comma separated distances from mid in percent, e.g. 0.1,0.5,1
+    bands: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    product: String,
+    #[serde(flatten)]
+    stats: MarketStats,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CircuitHealth {
+    state: String,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    retry_at_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VenueHealth {
+    venue: String,
+    status: String,
+    latency_ms: u128,
+    bid_levels: usize,
+    ask_levels: usize,
+    error: Option<String>,
+    error_message: Option<String>,
+    // Set when the venue answered with an error status
+    http_status: Option<u16>,
+    error_code: Option<String>,
+    retryable: bool,
+    // Circuit breaker state, absent when the breaker is disabled or never saw the venue
+    circuit: Option<CircuitHealth>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProductHealth {
+    product: String,
+    // Time of the most recent round and of the most recent successful one
+    updated_at_ms: u64,
+    succeeded_at_ms: Option<u64>,
+    // Age of the book being served, absent before the first successful round
+    age_ms: Option<u64>,
+    // No successful round within the maximum feed age
+    stale: bool,
+    last_error: Option<String>,
+    // Venues of the most recent round, failed rounds included
+    venues: Vec<VenueHealth>,
+}
+
+// Latest successful aggregation of a feed, or 503 until the first round succeeds
+fn latest_report(feed: &BookFeed) -> Result<Arc<AggregationReport>, ApiError> {
+    feed.latest().ok_or_else(|| no_book_yet(feed))
+}
+
+// Latest successful aggregation of a feed together with its age, read at once
+fn latest_report_with_freshness(
+    state: &AppState,
+    feed: &BookFeed,
+) -> Result<(Arc<AggregationReport>, Freshness), ApiError> {
+    let updates = feed.subscribe();
+    let feed_state = updates.borrow();
+    let report = feed_state.report.clone().ok_or_else(|| no_book_yet(feed))?;
+    let age_ms = feed_state.report_age_ms(unix_time_ms());
+    let freshness = Freshness {
+        age_ms: age_ms.unwrap_or_default(),
+        stale: state.is_stale(age_ms),
+    };
+    Ok((report, freshness))
+}
+
+fn no_book_yet(feed: &BookFeed) -> ApiError {
+    ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!("No order book available yet for {}", feed.product()),
+    )
+}
+
+// GET /book/{product}?depth=N&bucket=10
+pub async fn get_book(
+    State(state): State<Arc<AppState>>,
+    Path(product): Path<String>,
+    Query(query): Query<BookQuery>,
+) -> Result<Json<BookResponse>, ApiError> {
+    let width = match query.bucket {
+        Some(bucket) => Some(
+            bucket
+                .parse::<BucketWidth>()
+                .map_err(|error| ApiError::new(StatusCode::BAD_REQUEST, error.to_string()))?,
+        ),
+        None => None,
+    };
+    let feed = state.feed(&product)?;
+    let (report, freshness) = latest_report_with_freshness(&state, feed)?;
+    let depth = query.depth.unwrap_or(DEFAULT_BOOK_DEPTH);
+    let book = match width {
+        Some(width) => BookView::Buckets(report.book.bucketed(width, depth)),
+        None => BookView::Levels(report.book.top_n(depth)),
+    };
+    Ok(Json(BookResponse {
+        product: feed.product().to_string(),
+        freshness,
+        book,
+    }))
+}
+
+// GET /quote/{product}?side=buy&qty=10
+pub async fn get_quote(
+    State(state): State<Arc<AppState>>,
+    Path(product): Path<String>,
+    Query(query): Query<QuoteQuery>,
+) -> Result<Json<QuoteResponse>, ApiError> {
+    if query.qty.is_nan() || query.qty <= 0.0 {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "qty must be a positive number",
+        ));
+    }
+    let feed = state.feed(&product)?;
+    let (report, freshness) = latest_report_with_freshness(&state, feed)?;
+    let fills = report.book.calculate_best_offer(query.side, query.qty)?;
+    Ok(Json(QuoteResponse {
+        product: feed.product().to_string(),
+        freshness,
+        side: query.side,
+        summary: FillSummary::from_fills(&fills),
+        fills,
+    }))
+}
+
+// GET /impact/{product}?sizes=1,5,10,50
+pub async fn get_impact(
+    State(state): State<Arc<AppState>>,
+    Path(product): Path<String>,
+    Query(query): Query<ImpactQuery>,
+) -> Result<Json<ImpactResponse>, ApiError> {
+    let sizes = match query.sizes {
+        Some(sizes) => sizes
+            .split(',')
+            .map(|size| match size.trim().parse::<f64>() {
+                Ok(size) if size > 0.0 => Ok(size),
+                _ => Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "sizes must be comma separated positive numbers",
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => DEFAULT_IMPACT_SIZES.to_vec(),
+    };
+    let feed = state.feed(&product)?;
+    let report = latest_report(feed)?;
+    Ok(Json(ImpactResponse {
+        product: feed.product().to_string(),
+        curve: report.book.impact_curve(&sizes)?,
+    }))
+}
+
+// GET /stats/{product}?levels=5&bands=0.1,0.5,1
+pub async fn get_stats(
+    State(state): State<Arc<AppState>>,
+    Path(product): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<StatsResponse>, ApiError> {
+    let mut config = AnalyticsConfig::default();
+    if let Some(levels) = query.levels {
+        config.imbalance_levels = levels;
+    }
+    if let Some(bands) = query.bands {
+        config.depth_bands_pct = bands
+            .split(',')
+            .map(|band| match band.trim().parse::<f64>() {
+                Ok(band) if band >= 0.0 => Ok(band),
+                _ => Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "bands must be comma separated percentages",
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+    let feed = state.feed(&product)?;
+    let (report, previous) = feed
+        .latest_with_previous()
+        .ok_or_else(|| no_book_yet(feed))?;
+    Ok(Json(StatsResponse {
+        product: feed.product().to_string(),
+        stats: MarketStats::new(&report, previous.as_deref(), &config),
+    }))
+}
+
+// GET /venues/health, 503 when any feed is stale
+pub async fn get_venues_health(
+    State(state): State<Arc<AppState>>,
+) -> (StatusCode, Json<Vec<ProductHealth>>) {
+    let now_ms = unix_time_ms();
+    let mut products: Vec<ProductHealth> = state
+        .feeds()
+        .map(|feed| {
+            let feed_state = feed.state();
+            let age_ms = feed_state.report_age_ms(now_ms);
+            let venues = feed_state
+                .venues
+                .iter()
+                .map(|venue| VenueHealth {
+                    venue: venue.venue.clone(),
+                    status: venue.status.to_string(),
+                    latency_ms: venue.latency.as_millis(),
+                    bid_levels: venue.bid_levels,
+                    ask_levels: venue.ask_levels,
+                    error: venue.error.map(|kind| kind.to_string()),
+                    error_message: venue.error_message.clone(),
+                    http_status: venue.venue_error.as_ref().map(|error| error.status),
+                    error_code: venue
+                        .venue_error
+                        .as_ref()
+                        .and_then(|error| error.code.clone()),
+                    retryable: venue.retryable,
+                    circuit: feed_state
+                        .health
+                        .iter()
+                        .find(|circuit| circuit.venue == venue.venue)
+                        .map(|circuit| CircuitHealth {
+                            state: circuit.state.to_string(),
+                            consecutive_failures: circuit.consecutive_failures,
+                            last_error: circuit.last_error.map(|kind| kind.to_string()),
+                            retry_at_ms: circuit.retry_at_ms,
+                        }),
+                })
+                .collect();
+            ProductHealth {
+                product: feed.product().to_string(),
+                updated_at_ms: feed_state.updated_at_ms,
+                succeeded_at_ms: feed_state.succeeded_at_ms,
+                age_ms,
+                stale: state.is_stale(age_ms),
+                last_error: feed_state.last_error,
+                venues,
+            }
+        })
+        .collect();
+    products.sort_by(|a, b| a.product.cmp(&b.product));
+    let status = if products.iter().any(|product| product.stale) {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (status, Json(products))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggregator::OrderBookAggregator,
+        data_providers::{DataProvider, mock::MockProvider},
+        error::AggregatorError,
+        feed::BookFeed,
+        metrics::Metrics,
+        server::serve,
+        types::{Exchange, Product},
+    };
+    use async_trait::async_trait;
+    use serde_json::Value;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+    use tokio::net::TcpListener;
+
+    // Start a server on an ephemeral port and wait for its first aggregation
+    async fn start_server() -> String {
+        let mut book = OrderBook::new(Exchange::Coinbase);
+        book.add_bid(99.0, 1.0);
+        book.add_bid(98.0, 2.0);
+        book.add_ask(101.0, 1.0);
+        book.add_ask(102.0, 2.0);
+        let provider = Arc::new(MockProvider::new("Coinbase", book));
+        let metrics = Arc::new(Metrics::new());
+        let aggregator = OrderBookAggregator::new(vec![provider], Product::BTCUSD)
+            .with_metrics(Arc::clone(&metrics));
+        let feed = BookFeed::spawn(aggregator, Duration::from_secs(60));
+        let mut updates = feed.subscribe();
+        updates.changed().await.unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let state = AppState::new(vec![feed]).with_metrics(metrics);
+        tokio::spawn(serve(listener, Arc::new(state)));
+        format!("http://{}", address)
+    }
+
+    async fn get(url: String) -> (StatusCode, Value) {
+        let response = reqwest::get(url).await.unwrap();
+        let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+        (status, response.json().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_get_book() {
+        let base = start_server().await;
+        let (status, body) = get(format!("{}/book/btc-usd?depth=1", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["product"], "BTC-USD");
+        assert_eq!(body["bids"].as_array().unwrap().len(), 1);
+        assert_eq!(body["bids"][0]["price"], "99");
+        assert_eq!(body["asks"][0]["price"], "101");
+        assert!(body["age_ms"].is_u64());
+        assert_eq!(body["stale"], false);
+
+        let (status, _) = get(format!("{}/book/ETH-USD", base)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_get_bucketed_book() {
+        let base = start_server().await;
+        let (status, body) = get(format!("{}/book/BTC-USD?bucket=5", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["width"], "5");
+        assert_eq!(body["bids"][0]["price"], "95");
+        assert_eq!(body["bids"][0]["quantity"], "3");
+        assert_eq!(body["asks"][0]["price"], "105");
+        assert_eq!(body["asks"][0]["exchanges"][0]["exchange"], "coinbase");
+
+        let (status, _) = get(format!("{}/book/BTC-USD?bucket=abc", base)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_quote() {
+        let base = start_server().await;
+        let (status, body) = get(format!("{}/quote/BTCUSD?side=buy&qty=2", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["notional"], "203");
+        assert_eq!(body["fills"][0]["exchange"], "coinbase");
+        assert_eq!(body["fills"].as_array().unwrap().len(), 2);
+        assert_eq!(body["stale"], false);
+
+        let (status, _) = get(format!("{}/quote/BTCUSD?side=sell&qty=10", base)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_get_impact() {
+        let base = start_server().await;
+        let (status, body) = get(format!("{}/impact/BTC-USD?sizes=2,1,5", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["mid"], "100");
+        assert_eq!(body["buy"][1]["size"], "2");
+        assert_eq!(body["buy"][1]["notional"], "203");
+        assert_eq!(body["buy"][1]["venues"][0]["exchange"], "coinbase");
+        assert_eq!(body["sell"][2]["complete"], false);
+
+        let (status, _) = get(format!("{}/impact/BTC-USD?sizes=1,x", base)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_get_stats() {
+        let base = start_server().await;
+        let (status, body) = get(format!("{}/stats/BTC-USD?levels=1&bands=2", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["consolidated"]["spread_bps"], 200.0);
+        assert_eq!(body["consolidated"]["depth_imbalance"], 0.0);
+        // A single round so far, no order flow to measure yet
+        assert_eq!(body["consolidated"]["order_flow_imbalance"], Value::Null);
+        assert_eq!(body["consolidated"]["depth"][0]["bid_quantity"], "3");
+        assert_eq!(body["venues"][0]["venue"], "Coinbase");
+        assert_eq!(body["venues"][0]["microprice"], "100");
+    }
+
+    #[tokio::test]
+    async fn test_get_metrics() {
+        let base = start_server().await;
+        let response = reqwest::get(format!("{}/metrics", base)).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        let text = response.text().await.unwrap();
+        assert!(text.contains(r#"orderbook_spread_bps{product="BTC-USD",venue="agg"} 200.0"#));
+        assert!(text.contains("# TYPE orderbook_venue_fetch_duration_seconds histogram"));
+    }
+
+    #[tokio::test]
+    async fn test_get_venues_health() {
+        let base = start_server().await;
+        let (status, body) = get(format!("{}/venues/health", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        let venue = &body[0]["venues"][0];
+        assert_eq!(venue["venue"], "Coinbase");
+        assert_eq!(venue["status"], "success");
+        assert_eq!(venue["bid_levels"], 2);
+        assert_eq!(body[0]["stale"], false);
+    }
+
+    // Venue that answers its first fetch and fails every later one
+    struct FailsAfterFirst {
+        book: OrderBook,
+        fetched: AtomicBool,
+    }
+
+    #[async_trait]
+    impl DataProvider for FailsAfterFirst {
+        fn name(&self) -> &str {
+            "Gemini"
+        }
+
+        async fn fetch_order_book(&self, _: Product) -> Result<OrderBook, AggregatorError> {
+            if self.fetched.swap(true, Ordering::SeqCst) {
+                return Err(AggregatorError::ExchangeError("maintenance".to_string()));
+            }
+            Ok(self.book.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_venues_health_reports_failed_rounds() {
+        let mut book = OrderBook::new(Exchange::Gemini);
+        book.add_bid(99.0, 1.0);
+        book.add_ask(101.0, 1.0);
+        let provider = Arc::new(FailsAfterFirst {
+            book,
+            fetched: AtomicBool::new(false),
+        });
+        let aggregator = OrderBookAggregator::new(vec![provider], Product::BTCUSD);
+        let feed = BookFeed::spawn(aggregator, Duration::from_millis(10));
+        let mut updates = feed.subscribe();
+        updates
+            .wait_for(|state| state.last_error.is_some())
+            .await
+            .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        // With no allowed age any served book is stale, however fast the test runs
+        let state = AppState::new(vec![feed]).with_max_feed_age(Duration::ZERO);
+        tokio::spawn(serve(listener, Arc::new(state)));
+        let (status, body) = get(format!("http://{}/venues/health", address)).await;
+        // The last good book is past the limit and the venue shows the failed round
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body[0]["stale"], true);
+        assert!(body[0]["age_ms"].is_u64());
+        assert_eq!(body[0]["last_error"], "Failed to aggregate order books");
+        let venue = &body[0]["venues"][0];
+        assert_eq!(venue["status"], "failed");
+        assert_eq!(venue["error_message"], "maintenance");
+
+        // The book is still served, flagged as stale
+        let (status, body) = get(format!("http://{}/book/BTC-USD", address)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["bids"][0]["price"], "99");
+        assert_eq!(body["stale"], true);
+        let (_, body) = get(format!("http://{}/quote/BTC-USD?side=buy&qty=1", address)).await;
+        assert_eq!(body["stale"], true);
+    }
+}
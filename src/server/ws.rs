@@ -0,0 +1,248 @@
+use crate::{
+    feed::FeedState,
+    order_book::{BookDiff, OrderBook},
+    server::AppState,
+};
+use axum::{
+    extract::{
+        State, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::watch, time::timeout};
+
+// Levels per side streamed when the subscription does not set a depth
+pub const DEFAULT_STREAM_DEPTH: usize = 10;
+// Clients that cannot take a message within this time are disconnected
+pub const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum ClientMessage {
+    Subscribe {
+        product: String,
+        depth: Option<usize>,
+    },
+    Unsubscribe,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ServerMessage {
+    // Full top-N book, sent first after every subscribe
+    Snapshot {
+        product: String,
+        seq: u64,
+        #[serde(flatten)]
+        book: OrderBook,
+    },
+    // Levels changed since the previous message, quantity 0 removes a level
+    Update {
+        product: String,
+        seq: u64,
+        received_at_ms: u64,
+        #[serde(flatten)]
+        diff: BookDiff,
+    },
+    Error {
+        message: String,
+    },
+}
+
+// A client's subscription to one product feed.
+// Diffs are computed against the last book actually sent to this client, so a
+// slow client skips intermediate versions instead of queueing them.
+struct Subscription {
+    product: String,
+    depth: usize,
+    updates: watch::Receiver<FeedState>,
+    last_sent: Option<OrderBook>,
+    seq: u64,
+}
+
+impl Subscription {
+    // Next message to send for the current feed state, if anything changed
+    fn next_message(&mut self) -> Option<ServerMessage> {
+        let report = self.updates.borrow_and_update().report.clone()?;
+        let book = report.book.top_n(self.depth);
+        let message = match &self.last_sent {
+            None => ServerMessage::Snapshot {
+                product: self.product.clone(),
+                seq: self.seq,
+                book: book.clone(),
+            },
+            Some(previous) => {
+                let diff = book.diff(previous);
+                if diff.is_empty() {
+                    return None;
+                }
+                ServerMessage::Update {
+                    product: self.product.clone(),
+                    seq: self.seq,
+                    received_at_ms: book.received_at_ms,
+                    diff,
+                }
+            }
+        };
+        self.seq += 1;
+        self.last_sent = Some(book);
+        Some(message)
+    }
+}
+
+// GET /ws
+pub async fn ws_handler(ws: WebSocketUpgrade, State(state): State<Arc<AppState>>) -> Response {
+    ws.on_upgrade(move |socket| handle_socket(socket, state))
+}
+
+// Send one message, failing when the client is gone or too slow to keep up
+async fn send(socket: &mut WebSocket, message: &ServerMessage) -> bool {
+    let text = match serde_json::to_string(message) {
+        Ok(text) => text,
+        Err(_) => return false,
+    };
+    matches!(
+        timeout(SEND_TIMEOUT, socket.send(Message::Text(text.into()))).await,
+        Ok(Ok(()))
+    )
+}
+
+async fn handle_socket(mut socket: WebSocket, state: Arc<AppState>) {
+    let mut subscription: Option<Subscription> = None;
+    loop {
+        let message = tokio::select! {
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(ClientMessage::Subscribe { product, depth }) => match state.feed(&product) {
+                        Ok(feed) => {
+                            let mut new_subscription = Subscription {
+                                product: feed.product().to_string(),
+                                depth: depth.unwrap_or(DEFAULT_STREAM_DEPTH),
+                                updates: feed.subscribe(),
+                                last_sent: None,
+                                seq: 0,
+                            };
+                            let snapshot = new_subscription.next_message();
+                            subscription = Some(new_subscription);
+                            snapshot
+                        }
+                        Err(error) => Some(ServerMessage::Error { message: error.to_string() }),
+                    },
+                    Ok(ClientMessage::Unsubscribe) => {
+                        subscription = None;
+                        None
+                    }
+                    Err(error) => Some(ServerMessage::Error {
+                        message: format!("Invalid message: {}", error),
+                    }),
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => None,
+            },
+            changed = async {
+                match subscription.as_mut() {
+                    Some(subscription) => subscription.updates.changed().await,
+                    None => std::future::pending().await,
+                }
+            } => match changed {
+                Ok(()) => subscription.as_mut().and_then(Subscription::next_message),
+                // Feed is gone, nothing more will be published
+                Err(_) => break,
+            },
+        };
+        if let Some(message) = message
+            && !send(&mut socket, &message).await
+        {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggregator::OrderBookAggregator,
+        data_providers::mock::MockProvider,
+        feed::BookFeed,
+        server::serve,
+        types::{Exchange, Product},
+    };
+    use futures_util::{SinkExt, StreamExt};
+    use serde_json::Value;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{connect_async, tungstenite};
+
+    async fn next_json<S>(stream: &mut S) -> Value
+    where
+        S: StreamExt<Item = Result<tungstenite::Message, tungstenite::Error>> + Unpin,
+    {
+        loop {
+            if let tungstenite::Message::Text(text) = stream.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_then_updates() {
+        let mut book = OrderBook::new(Exchange::Coinbase);
+        book.add_bid(99.0, 1.0);
+        book.add_bid(98.0, 1.0);
+        book.add_ask(101.0, 1.0);
+        let provider = Arc::new(MockProvider::new("Coinbase", book));
+        let aggregator = OrderBookAggregator::new(vec![provider.clone()], Product::BTCUSD);
+        let feed = BookFeed::spawn(aggregator, Duration::from_millis(50));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, Arc::new(AppState::new(vec![feed]))));
+
+        let (mut socket, _) = connect_async(format!("ws://{}/ws", address)).await.unwrap();
+        socket
+            .send(tungstenite::Message::Text(
+                r#"{"op":"subscribe","product":"BTC-USD","depth":1}"#.into(),
+            ))
+            .await
+            .unwrap();
+
+        let snapshot = next_json(&mut socket).await;
+        assert_eq!(snapshot["type"], "snapshot");
+        assert_eq!(snapshot["seq"], 0);
+        assert_eq!(snapshot["bids"].as_array().unwrap().len(), 1);
+        assert_eq!(snapshot["bids"][0]["price"], "99");
+
+        // Only the best bid grows, a change to the 98 level would be outside depth 1
+        provider.update_book(|book| {
+            book.add_bid(99.0, 1.0);
+            book.add_bid(98.0, 1.0);
+        });
+        let update = next_json(&mut socket).await;
+        assert_eq!(update["type"], "update");
+        assert_eq!(update["seq"], 1);
+        assert_eq!(update["bids"].as_array().unwrap().len(), 1);
+        assert_eq!(update["bids"][0]["price"], "99");
+        assert!(update["asks"].as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_product() {
+        let aggregator = OrderBookAggregator::new(Vec::new(), Product::BTCUSD);
+        let feed = BookFeed::spawn(aggregator, Duration::from_secs(60));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, Arc::new(AppState::new(vec![feed]))));
+
+        let (mut socket, _) = connect_async(format!("ws://{}/ws", address)).await.unwrap();
+        socket
+            .send(tungstenite::Message::Text(
+                r#"{"op":"subscribe","product":"DOGE-USD"}"#.into(),
+            ))
+            .await
+            .unwrap();
+        let error = next_json(&mut socket).await;
+        assert_eq!(error["type"], "error");
+    }
+}
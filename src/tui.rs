@@ -0,0 +1,324 @@
+use crate::{
+    error::AggregatorError,
+    feed::FeedState,
+    order_book::{FillSummary, OrderBook},
+    types::{Exchange, Product, Side},
+};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table},
+};
+use std::time::Duration;
+use tokio::sync::watch;
+
+// Longest wait for a key press before the screen is redrawn with the latest feed state
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Ladder color of each venue
+fn venue_color(exchange: Exchange) -> Color {
+    match exchange {
+        Exchange::Coinbase => Color::Blue,
+        Exchange::Gemini => Color::Cyan,
+        Exchange::AggregatedExchange => Color::White,
+    }
+}
+
+// Terminal view of a live feed: ladder, venue health and a quote for a typed quantity
+pub struct App {
+    product: Product,
+    depth: usize,
+    updates: watch::Receiver<FeedState>,
+    side: Side,
+    // Quantity as typed, parsed on every redraw
+    quantity: String,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(product: Product, depth: usize, updates: watch::Receiver<FeedState>) -> Self {
+        App {
+            product,
+            depth,
+            updates,
+            side: Side::Buy,
+            quantity: String::new(),
+            quit: false,
+        }
+    }
+
+    // Draw and handle keys until the user quits. Blocking, run it off the async runtime.
+    pub fn run(mut self, terminal: &mut DefaultTerminal) -> Result<(), AggregatorError> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(POLL_INTERVAL)?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+            {
+                self.on_key(key.code, key.modifiers);
+            }
+        }
+        Ok(())
+    }
+
+    fn on_key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char(c) if c.is_ascii_digit() || c == '.' => self.quantity.push(c),
+            KeyCode::Backspace => {
+                self.quantity.pop();
+            }
+            KeyCode::Tab | KeyCode::Char('s') => {
+                self.side = match self.side {
+                    Side::Buy => Side::Sell,
+                    Side::Sell => Side::Buy,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        let state = self.updates.borrow().clone();
+        let book = state.report.as_ref().map(|report| &report.book);
+
+        let [header, body, quote] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(6),
+        ])
+        .areas(frame.area());
+        let [ladder, venues] =
+            Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .areas(body);
+
+        frame.render_widget(self.header(book, &state), header);
+        self.draw_ladder(frame, ladder, book);
+        self.draw_venues(frame, venues, &state);
+        frame.render_widget(self.quote_panel(book), quote);
+    }
+
+    fn header(&self, book: Option<&OrderBook>, state: &FeedState) -> Paragraph<'static> {
+        let mut spans = vec![Span::styled(
+            self.product.to_string(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )];
+        if let Some(book) = book
+            && let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask())
+        {
+            let spread = ask.price.0 - bid.price.0;
+            let mid = (ask.price.0 + bid.price.0) / 2.0;
+            spans.push(Span::raw(format!(
+                "   bid {:.2}  ask {:.2}  spread {:.2}  mid {:.2}",
+                bid.price.0, ask.price.0, spread, mid
+            )));
+        }
+        if let Some(error) = &state.last_error {
+            spans.push(Span::styled(
+                format!("   last round failed: {}", error),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        Paragraph::new(Line::from(spans)).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("q quit, tab switch side, type a quantity"),
+        )
+    }
+
+    fn draw_ladder(&self, frame: &mut Frame, area: Rect, book: Option<&OrderBook>) {
+        let rows: Vec<Row> = match book {
+            Some(book) => {
+                let asks = book.asks.values().take(self.depth).rev();
+                let bids = book.bids.values().rev().take(self.depth);
+                asks.map(|level| (Color::Red, level))
+                    .chain(bids.map(|level| (Color::Green, level)))
+                    .map(|(side_color, level)| {
+                        // A price quoted by several venues lists each with its own quantity
+                        let venues: Vec<Span> = if level.venues.is_empty() {
+                            vec![Span::styled(
+                                level.exchange.to_string(),
+                                Style::default().fg(venue_color(level.exchange)),
+                            )]
+                        } else {
+                            level
+                                .venues
+                                .iter()
+                                .map(|venue| {
+                                    Span::styled(
+                                        format!("{} {}  ", venue.exchange, venue.quantity),
+                                        Style::default().fg(venue_color(venue.exchange)),
+                                    )
+                                })
+                                .collect()
+                        };
+                        Row::new(vec![
+                            Cell::from(format!("{:.2}", level.price.0))
+                                .style(Style::default().fg(side_color)),
+                            Cell::from(format!("{:.8}", level.quantity.0)),
+                            Cell::from(Line::from(venues)),
+                        ])
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(14),
+                Constraint::Length(16),
+                Constraint::Min(8),
+            ],
+        )
+        .header(Row::new(vec!["PRICE", "QUANTITY", "VENUE"]))
+        .block(Block::default().borders(Borders::ALL).title("Ladder"));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_venues(&self, frame: &mut Frame, area: Rect, state: &FeedState) {
+        let rows: Vec<Row> = state
+            .venues
+            .iter()
+            .map(|venue| {
+                let status_color = if venue.is_success() {
+                    Color::Green
+                } else {
+                    Color::Red
+                };
+                Row::new(vec![
+                    Cell::from(venue.venue.clone()),
+                    Cell::from(venue.status.to_string()).style(Style::default().fg(status_color)),
+                    Cell::from(format!("{} ms", venue.latency.as_millis())),
+                    Cell::from(venue.error.map(|kind| kind.to_string()).unwrap_or_default()),
+                ])
+            })
+            .collect();
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(9),
+                Constraint::Min(8),
+            ],
+        )
+        .header(Row::new(vec!["VENUE", "STATUS", "LATENCY", "ERROR"]))
+        .block(Block::default().borders(Borders::ALL).title("Venues"));
+        frame.render_widget(table, area);
+    }
+
+    fn quote_panel(&self, book: Option<&OrderBook>) -> Paragraph<'static> {
+        let mut lines = vec![Line::from(format!("{} {}_", self.side, self.quantity))];
+        let result = match (book, self.quantity.parse::<f64>()) {
+            (None, _) => Err("waiting for the first book".to_string()),
+            (_, Err(_)) => Err(String::new()),
+            (Some(_), Ok(quantity)) if quantity <= 0.0 => Err(String::new()),
+            (Some(book), Ok(quantity)) => book
+                .calculate_best_offer(self.side, quantity)
+                .map_err(|error| error.to_string()),
+        };
+        match result {
+            Ok(fills) => {
+                let summary = FillSummary::from_fills(&fills);
+                // A level quoted by several venues yields one fill per venue at the same price
+                let levels = fills.chunk_by(|a, b| a.price == b.price).count();
+                lines.push(Line::from(format!(
+                    "average {:.2}  notional {:.2}  over {} level(s)",
+                    summary.average_price, summary.notional, levels
+                )));
+                // Share of the quantity filled on each venue, in ladder colors
+                let mut shares: Vec<Span> = Vec::new();
+                for exchange in Exchange::VENUES {
+                    let quantity: f64 = fills
+                        .iter()
+                        .filter(|fill| fill.exchange == exchange.to_string())
+                        .map(|fill| fill.quantity)
+                        .sum();
+                    if quantity > 0.0 {
+                        shares.push(Span::styled(
+                            format!("{} {:.8}  ", exchange, quantity),
+                            Style::default().fg(venue_color(exchange)),
+                        ));
+                    }
+                }
+                lines.push(Line::from(shares));
+            }
+            Err(message) => lines.push(Line::styled(message, Style::default().fg(Color::Red))),
+        }
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Quote"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregator::AggregationReport;
+    use ratatui::{Terminal, backend::TestBackend};
+    use std::sync::Arc;
+
+    fn app() -> App {
+        let mut book = OrderBook::new(Exchange::AggregatedExchange);
+        book.add_bid(99.0, 1.0);
+        let mut ask = OrderBook::new(Exchange::Gemini);
+        ask.add_ask(101.0, 2.0);
+        book.merge(&ask);
+        let mut shared = OrderBook::new(Exchange::Coinbase);
+        shared.add_ask(101.0, 1.0);
+        book.merge(&shared);
+        let state = FeedState {
+            report: Some(Arc::new(AggregationReport {
+                book,
+                venues: Vec::new(),
+                venue_books: Vec::new(),
+                crossed: None,
+            })),
+            previous: None,
+            venues: Vec::new(),
+            last_error: None,
+            updated_at_ms: 0,
+            succeeded_at_ms: Some(0),
+            health: Vec::new(),
+        };
+        let (_, updates) = watch::channel(state);
+        App::new(Product::BTCUSD, 10, updates)
+    }
+
+    fn render(app: &App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 24)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_header_and_ladder() {
+        let screen = render(&app());
+        assert!(screen.contains("spread 2.00  mid 100.00"));
+        assert!(screen.contains("101.00"));
+        assert!(screen.contains("gemini 2  coinbase 1"));
+    }
+
+    #[test]
+    fn test_quote_follows_typed_quantity() {
+        let mut app = app();
+        for key in ['1', '.', '5'] {
+            app.on_key(KeyCode::Char(key), KeyModifiers::NONE);
+        }
+        let screen = render(&app);
+        assert!(screen.contains("average 101.00  notional 151.50  over 1 level(s)"));
+        assert!(screen.contains("coinbase 0.50000000"));
+
+        app.on_key(KeyCode::Tab, KeyModifiers::NONE);
+        assert!(render(&app).contains("Insufficient liquidity"));
+    }
+}
@@ -1,11 +1,21 @@
+use crate::error::AggregatorError;
+use serde::{Deserialize, Serialize};
+use std::{
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 // Supported products for aggregation
 #[allow(clippy::upper_case_acronyms)]
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Product {
     BTCUSD,
 }
 
 impl Product {
+    // All products the aggregator knows about
+    pub const ALL: [Product; 1] = [Product::BTCUSD];
+
     pub fn to_coinbase_symbol(&self) -> &str {
         match self {
             Product::BTCUSD => "BTC-USD",
@@ -19,19 +29,85 @@ impl Product {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl std::fmt::Display for Product {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_coinbase_symbol())
+    }
+}
+
+// Accepts both the dashed (BTC-USD) and the compact (BTCUSD) form, in any case
+impl FromStr for Product {
+    type Err = AggregatorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Product::ALL
+            .into_iter()
+            .find(|product| {
+                s.eq_ignore_ascii_case(product.to_coinbase_symbol())
+                    || s.eq_ignore_ascii_case(product.to_gemini_symbol())
+            })
+            .ok_or_else(|| AggregatorError::UnknownProduct(s.to_string()))
+    }
+}
+
+// Side of an order taking liquidity from the book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl std::fmt::Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Side::Buy => write!(f, "buy"),
+            Side::Sell => write!(f, "sell"),
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Exchange {
     Coinbase,
     Gemini,
+    #[serde(rename = "agg")]
     AggregatedExchange,
 }
 
-impl Exchange {
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for Exchange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Exchange::Coinbase => "coinbase".to_string(),
-            Exchange::Gemini => "gemini".to_string(),
-            Exchange::AggregatedExchange => "agg".to_string(),
+            Exchange::Coinbase => write!(f, "coinbase"),
+            Exchange::Gemini => write!(f, "gemini"),
+            Exchange::AggregatedExchange => write!(f, "agg"),
         }
     }
 }
+
+impl Exchange {
+    // Venues order books are fetched from
+    pub const VENUES: [Exchange; 2] = [Exchange::Coinbase, Exchange::Gemini];
+}
+
+// Accepts venue names in any case, the aggregated book is not a venue
+impl FromStr for Exchange {
+    type Err = AggregatorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Exchange::VENUES
+            .into_iter()
+            .find(|exchange| s.eq_ignore_ascii_case(&exchange.to_string()))
+            .ok_or_else(|| AggregatorError::UnknownExchange(s.to_string()))
+    }
+}
+
+// Current wall clock time in unix milliseconds
+pub fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,192 @@
+use order_book_aggregator::{
+    aggregator::OrderBookAggregator,
+    data_providers::{DataProvider, mock::MockProvider},
+    error::AggregatorError,
+    feed::BookFeed,
+    order_book::OrderBook,
+    server::{
+        AppState,
+        grpc::{self, proto, proto::order_book_service_client::OrderBookServiceClient},
+    },
+    types::{Exchange, Product},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+use tonic::{Code, transport::Channel};
+
+// Local stand-in for a venue with two levels per side
+fn venue(name: &str, exchange: Exchange, bid: f64, ask: f64) -> Arc<dyn DataProvider> {
+    let mut book = OrderBook::new(exchange);
+    book.add_bid(bid, 1.0);
+    book.add_bid(bid - 1.0, 2.0);
+    book.add_ask(ask, 1.0);
+    book.add_ask(ask + 1.0, 2.0);
+    Arc::new(MockProvider::new(name, book))
+}
+
+async fn start_server() -> OrderBookServiceClient<Channel> {
+    let providers = vec![
+        venue("Coinbase", Exchange::Coinbase, 100.0, 102.0),
+        venue("Gemini", Exchange::Gemini, 100.5, 101.5),
+    ];
+    let aggregator = OrderBookAggregator::new(providers, Product::BTCUSD);
+    let feed = BookFeed::spawn(aggregator, Duration::from_millis(100));
+    feed.subscribe().changed().await.unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(grpc::serve(listener, Arc::new(AppState::new(vec![feed]))));
+    OrderBookServiceClient::connect(format!("http://{}", address))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_get_book() {
+    let mut client = start_server().await;
+    let book = client
+        .get_book(proto::GetBookRequest {
+            product: "BTC-USD".to_string(),
+            depth: 2,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(book.product, "BTC-USD");
+    assert_eq!(book.bids.len(), 2);
+    assert_eq!(
+        (book.bids[0].price, book.bids[0].exchange.as_str()),
+        (100.5, "gemini")
+    );
+    assert_eq!(
+        (book.asks[0].price, book.asks[0].exchange.as_str()),
+        (101.5, "gemini")
+    );
+    let venues = &book.asks[1].venues;
+    assert_eq!(venues.len(), 1);
+    assert_eq!(
+        (venues[0].exchange.as_str(), venues[0].quantity),
+        ("coinbase", 1.0)
+    );
+
+    let status = client
+        .get_book(proto::GetBookRequest {
+            product: "ETH-USD".to_string(),
+            depth: 0,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn test_get_quote() {
+    let mut client = start_server().await;
+    let quote = client
+        .get_quote(proto::GetQuoteRequest {
+            product: "BTCUSD".to_string(),
+            side: proto::Side::Buy as i32,
+            quantity: 2.0,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    // 1 @ 101.5 on Gemini, then 1 @ 102 on Coinbase
+    assert_eq!(quote.fills.len(), 2);
+    assert_eq!(quote.notional, 203.5);
+
+    let status = client
+        .get_quote(proto::GetQuoteRequest {
+            product: "BTCUSD".to_string(),
+            side: proto::Side::Sell as i32,
+            quantity: 100.0,
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+}
+
+#[tokio::test]
+async fn test_get_impact_curve() {
+    let mut client = start_server().await;
+    let curve = client
+        .get_impact_curve(proto::GetImpactCurveRequest {
+            product: "BTC-USD".to_string(),
+            sizes: vec![2.0],
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(curve.mid, 101.0);
+    assert_eq!(curve.buy.len(), 1);
+    let point = &curve.buy[0];
+    assert_eq!(point.notional, 203.5);
+    assert!(point.complete);
+    assert_eq!(point.venues.len(), 2);
+
+    let curve = client
+        .get_impact_curve(proto::GetImpactCurveRequest {
+            product: "BTC-USD".to_string(),
+            sizes: Vec::new(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(curve.sell.len(), 4);
+}
+
+#[tokio::test]
+async fn test_stream_book() {
+    let mut client = start_server().await;
+    let mut stream = client
+        .stream_book(proto::StreamBookRequest {
+            product: "BTC-USD".to_string(),
+            depth: 1,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    for _ in 0..2 {
+        let book = stream.next().await.unwrap().unwrap();
+        assert_eq!(book.bids.len(), 1);
+        assert_eq!(book.asks[0].price, 101.5);
+    }
+}
+
+#[tokio::test]
+async fn test_stream_book_skips_failed_rounds() {
+    let mut book = OrderBook::new(Exchange::Coinbase);
+    book.add_bid(100.0, 1.0);
+    book.add_ask(101.0, 1.0);
+    let provider = Arc::new(MockProvider::new("Coinbase", book));
+    let aggregator = OrderBookAggregator::new(vec![provider.clone()], Product::BTCUSD);
+    let feed = BookFeed::spawn(aggregator, Duration::from_millis(50));
+    let mut updates = feed.subscribe();
+    updates.changed().await.unwrap();
+    // Every following round fails and keeps the first book as the latest
+    for _ in 0..100 {
+        provider.fail_next(AggregatorError::ExchangeError("down".to_string()));
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(grpc::serve(listener, Arc::new(AppState::new(vec![feed]))));
+    let mut client = OrderBookServiceClient::connect(format!("http://{}", address))
+        .await
+        .unwrap();
+    let mut stream = client
+        .stream_book(proto::StreamBookRequest {
+            product: "BTC-USD".to_string(),
+            depth: 1,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(stream.next().await.unwrap().unwrap().bids[0].price, 100.0);
+    // Several failed rounds go by without the same book being sent again
+    updates.changed().await.unwrap();
+    assert!(updates.borrow().last_error.is_some());
+    let next = tokio::time::timeout(Duration::from_millis(300), stream.next()).await;
+    assert!(next.is_err());
+}